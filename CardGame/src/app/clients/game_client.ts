@@ -18,7 +18,7 @@ export default class GameClient {
     });
   }
 
-  async discardAction(cardIndex: number, post_event: () => {}) {
+  async discardAction(cardId: string, post_event: () => {}) {
 
     await fetch('http://' + this.host + ':' + this.port + '/discard-card', {
       method: "POST",
@@ -27,7 +27,7 @@ export default class GameClient {
         'Content-Type': 'application/json',
       },
       body: JSON.stringify(
-        {'game_id': this.game_id, 'card_index': cardIndex}
+        {'game_id': this.game_id, 'card_id': cardId}
       )
     }).catch((e) => {alert("Could not discard."); throw e;} )
     .then((_)=>{
@@ -35,7 +35,7 @@ export default class GameClient {
     });
   }
 
-  async openAction(cards: number[]) {
+  async openAction(cards: string[]) {
     await fetch('http://' + this.host + ':' + this.port + '/player-open', {
       method: "POST",
       headers: {
@@ -43,7 +43,7 @@ export default class GameClient {
         'Content-Type': 'application/json',
       },
       body: JSON.stringify(
-        {'game_id': this.game_id, 'card_indices': cards}
+        {'game_id': this.game_id, 'card_ids': cards}
       )
     }).catch((e) => {alert("Could not Open. 3 sets of 3 are required if your opponent has not opened. 1 set of 3 is required if they have opened."); throw e;} )
     .then((_) => {
@@ -51,7 +51,7 @@ export default class GameClient {
     });
   }
 
-  async addPointsAction(cards: number[]) {
+  async addPointsAction(cards: string[]) {
     await fetch('http://' + this.host + ':' + this.port + '/player-add-points', {
       method: "POST",
       headers: {
@@ -59,7 +59,7 @@ export default class GameClient {
         'Content-Type': 'application/json',
       },
       body: JSON.stringify(
-        {'game_id': this.game_id, 'card_indices': cards}
+        {'game_id': this.game_id, 'card_ids': cards}
       )
     }).catch((e) => {alert("Could add points. Either a set of 3, cards already in the points deck, Joker, or 2 is required for this action."); throw e;} )
     .then((_) => {
@@ -67,7 +67,7 @@ export default class GameClient {
     });
   }
 
-  async pickupDiscardAction(cards: number[], post_event: () => {}) {
+  async pickupDiscardAction(cards: string[], post_event: () => {}) {
     await fetch('http://' + this.host + ':' + this.port + '/player-pickup-discard', {
       method: "POST",
       headers: {
@@ -75,7 +75,7 @@ export default class GameClient {
         'Content-Type': 'application/json',
       },
       body: JSON.stringify(
-        {'game_id': this.game_id, 'card_indices': cards}
+        {'game_id': this.game_id, 'card_ids': cards}
       )
     }).catch((e) => { alert("Could not pickup the discard pile, an error occured."); throw e;} )
     .then((e) => {
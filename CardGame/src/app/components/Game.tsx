@@ -14,7 +14,7 @@ import {DECK_CONTAINER_VIEW_STYLE, PLAYER_CONTAINER_VIEW_STYLE, GAME_ACTIONS_STY
 import {TITLE_STYLES, GAME_ACTION_STYLE, TEAM_CONTAINER_VIEW_STYLE} from '../styles/game_styles';
 import TeamPoints from '../models/TeamPoints';
 
-type CardType = {value: CARD_VALUE, suit: CARD_SUIT};
+type CardType = {value: CARD_VALUE, suit: CARD_SUIT, id?: string, deck?: number};
 type TeamPointsType = {
   'A'?: CardType[], '2'?: CardType[], '3'?: CardType[], '4'?: CardType[],
   '5'?: CardType[], '6'?: CardType[], '7'?: CardType[], '8'?: CardType[],
@@ -32,6 +32,7 @@ type GameState = {
     team_1_total_points: number,
     team_2_total_points: number,
     top_discard?: CardType,
+    discard_pile_frozen: boolean,
     turn: number,
   },
 };
@@ -67,6 +68,7 @@ export class Game extends Component {
         team_1_total_points: 0,
         team_2_total_points: 0,
         top_discard: undefined,
+        discard_pile_frozen: false,
         turn: 0,
       },
       round_over: false,
@@ -114,12 +116,12 @@ export class Game extends Component {
   /// Discards a single selected card
   /// Resets the did_draw value so the correct buttons are disabled
   discardAction() {
-    let selected_indices = this.getSelectedCards();
-    if (selected_indices.length != 1) {
+    let selected_ids = this.getSelectedCards();
+    if (selected_ids.length != 1) {
       alert("You must select ONE card to discard");
       return;
     }
-    this.client.discardAction(selected_indices[0], async () => {
+    this.client.discardAction(selected_ids[0], async () => {
       this.state.did_draw = false;
     });
   }
@@ -201,6 +203,7 @@ export class Game extends Component {
               <View style={{flexDirection: 'column'}}>
               <Deck/>
               <Card value={this.state.game_state.top_discard?.value as CARD_VALUE} suit={this.state.game_state.top_discard?.suit as CARD_SUIT} selected={false}/>
+              {this.state.game_state.discard_pile_frozen && <Text style={{fontSize: 12, fontWeight: 'bold'}}>Frozen</Text>}
               <View>
                 <Text style={TITLE_STYLES}>Player</Text>
                 <Text style={{fontSize: 15, fontWeight: 'bold'}}>{this.player_names[this.player_id]}</Text>
@@ -289,14 +292,14 @@ export class Game extends Component {
   /// Searches through the player's hand to find cards with a selected state of true
   ///
   /// ## Returns
-  /// All indices of selected cards
-  getSelectedCards(): number[] {
-    var indices = [];
+  /// The identifiers of all selected cards
+  getSelectedCards(): string[] {
+    var ids: string[] = [];
     for (var i = 0; i<this.state.selected.length; i++){
       if (this.state.selected[i])
-        indices.push(i);
+        ids.push(this.state.game_state.player_hand[i].card.id!);
     }
-    return indices;
+    return ids;
   }
 }
 
@@ -4,7 +4,7 @@ import { View, Text, StyleProp, ViewStyle, Button } from "react-native";
 import { FlatList } from "react-native-gesture-handler";
 import network_config from '../config/Config';
 
-type GameSession = {game_id: string, players: string[]}
+type GameSession = {game_id: string, invite_code: string, players: string[]}
 type WebSocketResponse = {response_type: string, data: any};
 
 class GameLobby extends Component {
@@ -16,7 +16,7 @@ class GameLobby extends Component {
 
   constructor(props: {route: any, navigation: any}) {
     super(props);
-    this.state = {game_id: props.route.params.game_session_id, players: []};
+    this.state = {game_id: '', invite_code: props.route.params.game_session_id, players: []};
     this.socket;
     this.name = props.route.params.username;
     this.connectToGame(props.route.params.username, props.route.params.game_session_id);
@@ -31,6 +31,7 @@ class GameLobby extends Component {
     };
     return <View style={{flex: 1, height: 2, alignContent: 'flex-start', flexDirection:'row'}}>
           <View style={{ backgroundColor: '#DAD7D7', width: '50%', height: '100%', padding: 10}}>
+          <Text style={{fontWeight: 'bold', padding: 10}}> Invite code: {this.state.invite_code}</Text>
           <Text style={{fontWeight: 'bold', padding: 10}}> Joined Players ({this.state.players.length}/4)</Text>
           <FlatList
             data={this.state.players}
@@ -90,7 +91,7 @@ class GameLobby extends Component {
     };
   }
 
-  connectToGame(username: string, game_id: string) {
+  connectToGame(username: string, invite_code: string) {
     fetch('http://' + this.host + ':' + this.port + '/game-register', {
       method: "POST",
       headers: {
@@ -98,11 +99,13 @@ class GameLobby extends Component {
         'Content-Type': 'application/json',
       },
       body: JSON.stringify(
-        {'game_identifier': game_id, 'player_username': username}
+        invite_code ? {'invite_code': invite_code, 'player_username': username} : {'player_username': username}
       )
     }).catch((e) => {alert("Could not join the game, room must be full."); throw e;} )
     .then((response) => response.json())
-    .then((json: { game_id: string, players: [string], max_capacity: number, url: string} ) => {
+    .then((json: { game_id: string, invite_code: string, players: [string]} ) => {
+      this.state.game_id = json.game_id;
+      this.state.invite_code = json.invite_code;
       this.state.players = [];
       json.players.forEach(element => this.state.players.push(element));
       this.forceUpdate();
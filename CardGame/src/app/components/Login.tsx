@@ -17,7 +17,7 @@ export default class Login extends Component {
   port = network_config.port;
   constructor(props: any) {
     super(props);
-    this.state = {username: uniqueNamesGenerator(config), game_session_id: "1"};
+    this.state = {username: uniqueNamesGenerator(config), game_session_id: ""};
   }
 
   render() {
@@ -31,7 +31,7 @@ export default class Login extends Component {
         />
         <TextInput         
             style={{height: 60, borderStyle: 'solid'}}
-            placeholder={"Game id: " + this.state.game_session_id} 
+            placeholder={"Invite code, leave empty to create a table"} 
             onChangeText={game_id => this.state = {username: this.state.username, game_session_id: game_id}}
             defaultValue={''}
         />
@@ -0,0 +1,516 @@
+use crate::gameplay::{DDPState, MeldGroup};
+use card_game_engine::models::deck::{Card, CardSuit, CardValue, Deck, DeckType};
+use card_game_engine::models::player::Player;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Decks shuffled together into the shoe
+pub const DECKS: u8 = 2;
+/// Cards of the shoe, each of them is always in a hand, a pile or a meld
+pub const SHOE_SIZE: usize = 108;
+
+/// Returns the shuffled shoe the game is dealt from, `DECKS` decks with
+/// their Jokers, along with the identifier of each of its cards
+///
+/// ## Purpose
+/// The engine shuffles the decks together without telling their cards apart,
+/// so the copies of each card are given their decks at random as the shoe is
+/// built, as if the decks had been marked before the shuffle. From then on an
+/// identifier only moves along with its card, see `CardIds`.
+///
+/// ## Returns
+/// The shoe and the identifiers of its cards, the next card drawn last
+pub fn shoe() -> (Deck, Vec<CardId>) {
+    let mut shoe = Deck::new(DeckType::WithJokers);
+    for _ in 1..DECKS {
+        shoe.extend(Deck::new(DeckType::WithJokers));
+    }
+    shoe.shuffle();
+
+    let mut decks = shoe_ids();
+    decks.shuffle(&mut rand::thread_rng());
+    let mut cards = shoe.clone();
+    let mut stock = Vec::with_capacity(SHOE_SIZE);
+    while let Some(card) = cards.draw_card() {
+        let id = match decks.iter().position(|id| id.matches(&card)) {
+            Some(i) => decks.swap_remove(i),
+            None => CardId::outside_shoe(card),
+        };
+        stock.push(id);
+    }
+    stock.reverse();
+    (shoe, stock)
+}
+
+/// Returns the identifier of every card of the shoe, each card
+/// tagged with the deck it comes from
+pub fn shoe_ids() -> Vec<CardId> {
+    let mut ids = Vec::with_capacity(SHOE_SIZE);
+    for deck in 0..DECKS {
+        let mut cards = Deck::new(DeckType::WithJokers);
+        while let Some(Card { value, suit }) = cards.draw_card() {
+            ids.push(CardId { value, suit, deck });
+        }
+    }
+    ids
+}
+
+/// Identifies a card of the shoe
+///
+/// ## Purpose
+/// The shoe holds two decks so identical cards come in more than one copy, the
+/// deck the card comes from tells them apart. A card keeps its identifier
+/// wherever it goes on the table, however the hands are sorted. A card found
+/// on the table without a place in the shoe is given the deck `DECKS`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CardId {
+    pub value: CardValue,
+    pub suit: CardSuit,
+    pub deck: u8,
+}
+
+impl CardId {
+    /// Returns whether the identifier can belong to the card
+    pub fn matches(&self, card: &Card) -> bool {
+        self.value == card.value && self.suit == card.suit
+    }
+
+    /// Identifies a card that doesn't come from the shoe
+    fn outside_shoe(card: Card) -> Self {
+        let Card { value, suit } = card;
+        Self {
+            value,
+            suit,
+            deck: DECKS,
+        }
+    }
+}
+
+impl fmt::Display for CardId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}-{:?}-{}", self.value, self.suit, self.deck)
+    }
+}
+
+/// A card taken out of a hand for the move being played
+#[derive(Clone, Debug)]
+struct SetAside {
+    seat: usize,
+    index: usize,
+    card: Card,
+    id: Option<CardId>,
+}
+
+/// Identifiers of the cards of the table, kept in the same order as the cards
+///
+/// ## Purpose
+/// The engine's cards can't carry their deck, so every place cards are kept in
+/// has its identifiers kept here, and every move carries the identifiers of
+/// the cards it moves to their new place along with them
+#[derive(Clone, Debug, Default)]
+pub struct CardIds {
+    /// The cards of each hand, in the order of the hand
+    pub hands: Vec<Vec<CardId>>,
+    /// The stock, the next card drawn last
+    pub stock: Vec<CardId>,
+    /// The discard pile, the top card last
+    pub discard_pile: Vec<CardId>,
+    /// The points of each team by value, in the order they were laid down
+    pub melds: Vec<HashMap<CardValue, Vec<CardId>>>,
+    /// The Queens of Spades each team laid down on their own
+    pub queens_of_spades: Vec<Vec<CardId>>,
+    /// Cards taken out of a hand for the move being played
+    set_aside: Vec<SetAside>,
+}
+
+impl CardIds {
+    /// Returns the identifiers of a table before any card is dealt
+    ///
+    /// ## Arguments
+    /// `stock` - The identifiers of the shoe, as returned by `shoe`
+    /// `seats` - The number of players
+    /// `teams` - The number of teams
+    pub fn new(stock: Vec<CardId>, seats: usize, teams: usize) -> Self {
+        Self {
+            hands: vec![Vec::new(); seats],
+            stock,
+            melds: vec![HashMap::new(); teams],
+            queens_of_spades: vec![Vec::new(); teams],
+            ..Self::default()
+        }
+    }
+
+    /// Moves the identifier of the card drawn from the stock to the hand
+    pub(crate) fn draw(&mut self, seat: usize) {
+        if let Some(id) = self.stock.pop() {
+            self.hands[seat].push(id);
+        }
+    }
+
+    /// Moves the identifier of the card drawn from the stock to the discard pile
+    pub(crate) fn turn_over_top_card(&mut self) {
+        if let Some(id) = self.stock.pop() {
+            self.discard_pile.push(id);
+        }
+    }
+
+    /// Follows the engine turning the discard pile over into an empty stock
+    /// to draw a card
+    ///
+    /// ## Purpose
+    /// The engine doesn't say where each card of the pile went, so its
+    /// identifiers are given back to the cards of the pile in the order the
+    /// cards now come. Only copies of a card that were both in the pile can
+    /// trade their identifiers.
+    ///
+    /// ## Arguments
+    /// `deck` - The deck once the card was drawn
+    /// `seat` - The player who drew the card
+    /// `drawn` - The card drawn
+    pub(crate) fn turn_over_discard_pile(&mut self, deck: &Deck, seat: usize, drawn: &Card) {
+        let mut pile = std::mem::take(&mut self.discard_pile);
+        let mut take = |card: &Card| match pile.iter().position(|id| id.matches(card)) {
+            Some(i) => pile.remove(i),
+            None => CardId::outside_shoe(card.clone()),
+        };
+
+        let mut cards = deck.clone();
+        let discard_pile: Vec<Card> = cards.take_discard_pile().drain(..).collect();
+        let mut stock = Vec::new();
+        while let Some(card) = cards.draw_card() {
+            stock.push(take(&card));
+        }
+        stock.reverse();
+
+        self.hands[seat].push(take(drawn));
+        self.discard_pile = discard_pile.iter().map(&mut take).collect();
+        self.stock = stock;
+    }
+
+    /// Moves the identifier of a card discarded from the hand to the discard pile
+    ///
+    /// ## Arguments
+    /// `seat` - The player holding the card
+    /// `index` - The position of the card in the hand
+    pub(crate) fn discard(&mut self, seat: usize, index: usize) {
+        if let Some(ids) = self.hands.get_mut(seat).filter(|ids| index < ids.len()) {
+            self.discard_pile.push(ids.remove(index));
+        }
+    }
+
+    /// Moves the identifiers of the discard pile to the hand, in the order
+    /// the engine adds the cards of the pile to it
+    pub(crate) fn pick_up_discard_pile(&mut self, seat: usize) {
+        let pile: Vec<CardId> = self.discard_pile.drain(..).collect();
+        self.hands[seat].extend(pile);
+    }
+
+    /// Moves the identifiers of the cards set aside to the points of the team
+    ///
+    /// ## Arguments
+    /// `team` - The team laying the cards down
+    /// `groups` - The groups added to the team's points
+    /// `queens` - The Queens of Spades laid down on their own
+    /// `picked_up` - The top card of the discard pile, when it was picked up with them
+    ///
+    /// ## Rules
+    /// * Each card takes the identifier of a card set aside the same as it,
+    ///     in the order the cards were set aside
+    /// * A card laid down without being set aside doesn't come from the shoe
+    pub(crate) fn lay_down(
+        &mut self,
+        team: usize,
+        groups: &[MeldGroup],
+        queens: &[Card],
+        picked_up: Option<CardId>,
+    ) {
+        let mut laid: Vec<CardId> = self
+            .set_aside
+            .drain(..)
+            .filter_map(|set_aside| set_aside.id)
+            .chain(picked_up)
+            .collect();
+        let mut take = |card: &Card| match laid.iter().position(|id| id.matches(card)) {
+            Some(i) => laid.remove(i),
+            None => CardId::outside_shoe(card.clone()),
+        };
+
+        for group in groups {
+            let ids: Vec<CardId> = group.cards.iter().map(&mut take).collect();
+            self.melds[team].entry(group.value).or_default().extend(ids);
+        }
+        let ids: Vec<CardId> = queens.iter().map(&mut take).collect();
+        self.queens_of_spades[team].extend(ids);
+    }
+
+    /// Puts the cards set aside back where they were in the hands
+    pub(crate) fn put_back(&mut self, players: &mut [Player]) {
+        let mut set_aside = std::mem::take(&mut self.set_aside);
+        set_aside.sort_by_key(|set_aside| set_aside.index);
+        for SetAside {
+            seat,
+            index,
+            card,
+            id,
+        } in set_aside
+        {
+            players[seat].hand.insert(index, card);
+            if let Some(id) = id {
+                self.hands[seat].insert(index.min(self.hands[seat].len()), id);
+            }
+        }
+    }
+
+    /// Returns every identifier given to a card of the table
+    fn placed(&self) -> impl Iterator<Item = &CardId> {
+        self.hands
+            .iter()
+            .flatten()
+            .chain(self.stock.iter())
+            .chain(self.discard_pile.iter())
+            .chain(self.melds.iter().flat_map(|melds| melds.values().flatten()))
+            .chain(self.queens_of_spades.iter().flatten())
+            .chain(
+                self.set_aside
+                    .iter()
+                    .filter_map(|set_aside| set_aside.id.as_ref()),
+            )
+    }
+}
+
+impl DDPState {
+    /// Compares the identifiers of the table with the cards of the shoe
+    ///
+    /// ## Purpose
+    /// Checks no move lost or duplicated a card, only meant for tests and
+    /// `debug_assert!`s as it counts the whole table
+    ///
+    /// ## Returns
+    /// Every identifier the table holds a different number of times than the
+    /// shoe, with the number of copies too many, or -1 when it is missing.
+    /// Empty when the `SHOE_SIZE` cards are all accounted for
+    pub fn unaccounted_cards(&self) -> Vec<(CardId, isize)> {
+        let mut counts: HashMap<String, (CardId, isize)> = shoe_ids()
+            .into_iter()
+            .map(|id| (id.to_string(), (id, -1)))
+            .collect();
+        for id in self.card_ids.placed() {
+            counts
+                .entry(id.to_string())
+                .or_insert_with(|| (id.clone(), 0))
+                .1 += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(_, count)| count)
+            .filter(|(_, copies)| *copies != 0)
+            .collect()
+    }
+
+    /// Returns the positions of the cards in the hand of the player
+    ///
+    /// ## Arguments
+    /// `seat` - The player holding the cards
+    /// `ids` - The identifiers of the cards, see `CardId`'s display
+    ///
+    /// ## Returns
+    /// The index of every card in the hand, None if a card isn't in the hand
+    /// or is given more than once
+    pub fn card_indices(&self, seat: usize, ids: &[String]) -> Option<Vec<usize>> {
+        let hand_ids = self.card_ids.hands.get(seat)?;
+        let mut indices: Vec<usize> = Vec::with_capacity(ids.len());
+        for id in ids {
+            let index = hand_ids.iter().position(|held| held.to_string() == *id)?;
+            if indices.contains(&index) {
+                return None;
+            }
+            indices.push(index);
+        }
+        Some(indices)
+    }
+
+    /// Sets cards of the hand of the player aside for the move being played,
+    /// the cards keep their identifiers wherever the move lays them down
+    ///
+    /// ## Arguments
+    /// `seat` - The player holding the cards
+    /// `indices` - The distinct positions of the cards in the hand
+    ///
+    /// ## Returns
+    /// The cards set aside, in the order of `indices`
+    pub fn remove_cards(&mut self, seat: usize, indices: &[usize]) -> Vec<Card> {
+        let hand = &mut self.default_state.players[seat].hand;
+        let ids = &mut self.card_ids.hands[seat];
+        let mut removed: Vec<SetAside> = Vec::with_capacity(indices.len());
+        let mut sorted = indices.to_vec();
+        sorted.sort_unstable();
+        for index in sorted.into_iter().rev() {
+            let id = if index < ids.len() {
+                Some(ids.remove(index))
+            } else {
+                None
+            };
+            removed.push(SetAside {
+                seat,
+                index,
+                card: hand.remove(index),
+                id,
+            });
+        }
+
+        // Kept in the order of `indices` so the cards laid down find their identifiers in it
+        let mut cards = Vec::with_capacity(indices.len());
+        for index in indices {
+            if let Some(i) = removed
+                .iter()
+                .position(|set_aside| set_aside.index == *index)
+            {
+                let set_aside = removed.swap_remove(i);
+                cards.push(set_aside.card.clone());
+                self.card_ids.set_aside.push(set_aside);
+            }
+        }
+        cards
+    }
+
+    /// Puts the cards set aside back where they were in the hands,
+    /// when the move they were set aside for can't be played
+    pub fn put_back_cards(&mut self) {
+        self.card_ids.put_back(&mut self.default_state.players);
+    }
+
+    /// Returns every card of the table: the hands, the stock, the discard pile and the melds
+    #[cfg(test)]
+    fn table_cards(&self) -> Vec<Card> {
+        let mut deck = self.default_state.deck.clone();
+        let mut cards: Vec<Card> = deck.take_discard_pile().drain(..).collect();
+        while let Some(card) = deck.draw_card() {
+            cards.push(card);
+        }
+        for player in self.default_state.players.iter() {
+            cards.extend(player.hand.iter().cloned());
+        }
+        for partners in self.partners.iter() {
+            cards.extend(partners.points_deck.values().flatten().cloned());
+            cards.extend(partners.queens_of_spades.iter().cloned());
+        }
+        cards
+    }
+}
+
+#[test]
+fn card_ids_follow_the_cards() {
+    use crate::gameplay::{DameDePiqueGameBuilder, PlayerMove};
+    use card_game_engine::builder::GameBuilder;
+    use card_game_engine::rules::GameRules;
+
+    let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+    // Every card of the shoe is given a single identifier from one of its decks
+    assert!(game.unaccounted_cards().is_empty());
+    for (hand, ids) in game
+        .default_state
+        .players
+        .iter()
+        .map(|player| &player.hand)
+        .zip(game.card_ids.hands.iter())
+    {
+        assert_eq!(hand.len(), ids.len());
+        assert!(hand.iter().zip(ids).all(|(card, id)| id.matches(card)));
+        assert!(ids.iter().all(|id| id.deck < DECKS));
+    }
+
+    let ids: Vec<String> = game.card_ids.hands[0]
+        .iter()
+        .map(CardId::to_string)
+        .collect();
+    assert_eq!(game.card_indices(0, &[ids[1].clone()]), Some(vec![1]));
+
+    // Unknown and repeated identifiers are refused
+    assert_eq!(game.card_indices(0, &["Seven-Hearts-9".into()]), None);
+    assert_eq!(
+        game.card_indices(0, &[ids[1].clone(), ids[1].clone()]),
+        None
+    );
+
+    // A discarded card takes its identifier to the discard pile
+    PlayerMove::Draw.handle_move(&mut game).unwrap();
+    let discarded = game.card_ids.hands[0][0].clone();
+    PlayerMove::Discard(0).handle_move(&mut game).unwrap();
+    assert_eq!(game.card_ids.discard_pile.last(), Some(&discarded));
+
+    // Cards set aside go back where they were when their move isn't played
+    let before = game.card_ids.hands[0].clone();
+    let cards = game.remove_cards(0, &[3, 0, 2]);
+    assert_eq!(cards.len(), 3);
+    assert_eq!(game.card_ids.hands[0].len(), before.len() - 3);
+    game.put_back_cards();
+    assert_eq!(game.card_ids.hands[0], before);
+
+    // And take their identifiers to the points they are laid down in
+    let cards = game.remove_cards(0, &[3, 0, 2]);
+    let group = MeldGroup::new(CardValue::Seven, cards);
+    game.card_ids.lay_down(0, &[group], &[], None);
+    assert_eq!(
+        game.card_ids.melds[0][&CardValue::Seven],
+        vec![before[3].clone(), before[0].clone(), before[2].clone()]
+    );
+    assert!(game.unaccounted_cards().is_empty());
+}
+
+#[test]
+fn drawn_cards_keep_their_deck() {
+    use crate::gameplay::{DameDePiqueGameBuilder, PlayerMove};
+    use card_game_engine::builder::GameBuilder;
+    use card_game_engine::rules::GameRules;
+
+    let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+    // Draws until the next card has its twin in the stock
+    let (next, twin) = loop {
+        let stock = &game.card_ids.stock;
+        let next = stock.last().unwrap().clone();
+        let twin = stock[..stock.len() - 1]
+            .iter()
+            .find(|id| id.value == next.value && id.suit == next.suit)
+            .cloned();
+        match twin {
+            Some(twin) => break (next, twin),
+            None => {
+                PlayerMove::Draw.handle_move(&mut game).unwrap();
+            }
+        }
+    };
+
+    PlayerMove::Draw.handle_move(&mut game).unwrap();
+    let turn = game.default_state.turn;
+    let hand = &game.default_state.players[turn].hand;
+    assert!(next.matches(hand.last().unwrap()));
+    assert_eq!(game.card_ids.hands[turn].last(), Some(&next));
+    assert!(game.card_ids.stock.contains(&twin));
+    assert!(!game.card_ids.stock.contains(&next));
+}
+
+#[test]
+fn table_cards_are_conserved() {
+    use crate::gameplay::{DameDePiqueGameBuilder, PlayerMove};
+    use card_game_engine::builder::GameBuilder;
+    use card_game_engine::rules::GameRules;
+
+    let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+    assert_eq!(game.table_cards().len(), SHOE_SIZE);
+    assert!(game.unaccounted_cards().is_empty());
+
+    PlayerMove::Draw.handle_move(&mut game).unwrap();
+    PlayerMove::Discard(0).handle_move(&mut game).unwrap();
+    assert_eq!(game.table_cards().len(), SHOE_SIZE);
+    assert!(game.unaccounted_cards().is_empty());
+
+    // A card lost from a hand is reported missing
+    let lost = game.card_ids.hands[1].remove(0);
+    assert_eq!(game.unaccounted_cards(), vec![(lost.clone(), -1)]);
+
+    // A card found twice is reported along with the number of copies too many
+    game.card_ids.hands[1].push(lost.clone());
+    game.card_ids.hands[2].push(lost.clone());
+    assert_eq!(game.unaccounted_cards(), vec![(lost, 1)]);
+}
@@ -0,0 +1,2 @@
+mod cards;
+pub use cards::{shoe, shoe_ids, CardId, CardIds, DECKS, SHOE_SIZE};
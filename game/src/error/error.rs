@@ -9,6 +9,7 @@ pub enum DameDePiqueError {
     InvalidDiscardOpeningHand(usize),
     PlayerCantAddPoints(usize),
     InvalidPoints,
+    NothingToUndo(usize),
 }
 
 impl fmt::Display for DameDePiqueError {
@@ -20,6 +21,7 @@ impl fmt::Display for DameDePiqueError {
             DameDePiqueError::InvalidOpeningHand(player_id) => write!(f, "Player {} cannot open", player_id),
             DameDePiqueError::PlayerCantAddPoints(player_id) => write!(f, "Player {} does not meet the requirements to add points", player_id),
             DameDePiqueError::InvalidPoints => write!(f, "The cards provided are not valid points for the game"),
+            DameDePiqueError::NothingToUndo(player_id) => write!(f, "Player {} has nothing to undo this turn", player_id),
         }
     }
 }
@@ -33,6 +35,7 @@ impl e::Error for DameDePiqueError {
             DameDePiqueError::InvalidOpeningHand(_) => "Player could not open",
             DameDePiqueError::InvalidPoints => "The cards provided are not valid points for the game",
             DameDePiqueError::PlayerCantAddPoints(_) => "Player could not add points",
+            DameDePiqueError::NothingToUndo(_) => "Player had nothing to undo",
         }
     }
 }
@@ -1,28 +1,414 @@
+use crate::cards::{shoe, CardIds};
 use crate::error::DameDePiqueError;
 use crate::partners::{Partners, WhoOpened};
 use card_game_engine::builder::GameBuilder;
-use card_game_engine::models::deck::{Card, CardSuit, CardValue, Deck, DeckType};
+use card_game_engine::models::deck::{Card, CardSuit, CardValue};
 use card_game_engine::models::player::Player;
 use card_game_engine::rules::{DefaultMove, GameRules, GameStatus};
 use card_game_engine::state::GameState;
 
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 
 const GAME_POINT_TOTAL: u16 = 1000;
+const PLAYER_COUNT: usize = 4;
+/// Sets required to open before the partner did
+const OPENING_SETS: usize = 3;
+/// Sets required to open once the partner opened
+const PARTNER_OPENED_SETS: usize = 1;
+/// Number of cards in a complete set
+const SET_SIZE: usize = 3;
 
 #[derive(Clone)]
 pub enum PlayerMove {
     Draw,
-    Open(Vec<Card>),
-    AddPoints(Vec<Card>),
+    Open(Vec<MeldGroup>),
+    AddPoints(Vec<MeldGroup>),
     TakeDiscardPile(Vec<Card>),
     Discard(usize),
+    /// Takes back the last `Open` or `AddPoints` of the turn
+    Undo,
+}
+
+/// Cards laid down together as the points of a single value
+///
+/// ## Rules
+/// * The wild Two's of a group count toward its value
+/// * A group of Two's holds loose wild cards, a group of Jokers holds Jokers
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeldGroup {
+    pub value: CardValue,
+    pub cards: Vec<Card>,
+}
+
+impl MeldGroup {
+    pub fn new(value: CardValue, cards: Vec<Card>) -> Self {
+        Self { value, cards }
+    }
+
+    /// Creates a group valued after its first card that isn't a Two,
+    /// a group of Two's only is a group of loose wild cards
+    pub fn with_cards(cards: Vec<Card>) -> Self {
+        let value = cards
+            .iter()
+            .map(|card| card.value)
+            .find(|value| *value != CardValue::Two)
+            .unwrap_or(CardValue::Two);
+        Self { value, cards }
+    }
+
+    /// Groups cards laid down without a grouping
+    ///
+    /// ## Purpose
+    /// Cards are grouped by value, then the Two's complete the sets closest
+    /// to complete first. Two's left over are grouped on their own.
+    ///
+    /// ## Arguments
+    /// `cards` - The cards being laid down
+    /// `melded` - The points of the team, values already melded need no wild card
+    ///
+    /// ## Returns
+    /// The groups the cards were sorted in
+    pub fn group_cards(cards: &[Card], melded: &HashMap<CardValue, Vec<Card>>) -> Vec<MeldGroup> {
+        let mut groups: Vec<MeldGroup> = Vec::new();
+        let mut twos = Vec::new();
+        let mut jokers = Vec::new();
+        for card in cards {
+            match card.value {
+                CardValue::Two => twos.push(card.clone()),
+                CardValue::Joker => jokers.push(card.clone()),
+                value => match groups.iter_mut().find(|group| group.value == value) {
+                    Some(group) => group.cards.push(card.clone()),
+                    None => groups.push(MeldGroup::new(value, vec![card.clone()])),
+                },
+            }
+        }
+
+        groups.sort_by_key(|group| Reverse(group.cards.len()));
+        for group in groups.iter_mut() {
+            if melded.contains_key(&group.value) {
+                continue;
+            }
+            while group.cards.len() < SET_SIZE {
+                match twos.pop() {
+                    Some(two) => group.cards.push(two),
+                    None => break,
+                }
+            }
+        }
+
+        if !twos.is_empty() {
+            groups.push(MeldGroup::new(CardValue::Two, twos));
+        }
+        if !jokers.is_empty() {
+            groups.push(MeldGroup::new(CardValue::Joker, jokers));
+        }
+        groups
+    }
+
+    /// Returns the amount of cards of the group's value
+    pub fn naturals(&self) -> usize {
+        self.cards
+            .iter()
+            .filter(|card| card.value == self.value)
+            .count()
+    }
+
+    /// Returns the amount of wild Two's completing the group
+    pub fn wilds(&self) -> usize {
+        match self.value {
+            CardValue::Two => 0,
+            _ => self
+                .cards
+                .iter()
+                .filter(|card| card.value == CardValue::Two)
+                .count(),
+        }
+    }
+
+    /// Returns whether every card of the group belongs in it
+    ///
+    /// ## Rules
+    /// * Every card is of the group's value
+    /// * Only Two's may stand in for another value
+    pub fn is_consistent(&self) -> bool {
+        match self.value {
+            CardValue::Two | CardValue::Joker => {
+                !self.cards.is_empty() && self.naturals() == self.cards.len()
+            }
+            _ => !self.cards.is_empty() && self.naturals() + self.wilds() == self.cards.len(),
+        }
+    }
+
+    /// Returns whether the group follows the wild card policy
+    ///
+    /// ## Arguments
+    /// `policy` - The limits on wild cards
+    /// `melded` - The cards the team already laid down for the group's value
+    pub fn respects(&self, policy: &WildCardPolicy, melded: &[Card]) -> bool {
+        match self.value {
+            CardValue::Joker => true,
+            CardValue::Two => policy.allow_pure_wild_melds,
+            value => {
+                let wilds = self.wilds()
+                    + melded
+                        .iter()
+                        .filter(|card| card.value == CardValue::Two)
+                        .count();
+                let naturals =
+                    self.naturals() + melded.iter().filter(|card| card.value == value).count();
+                policy.max_wilds_per_meld.map_or(true, |max| wilds <= max)
+                    && (policy.wilds_may_outnumber_naturals || wilds <= naturals)
+            }
+        }
+    }
+
+    /// Returns whether the group is a complete set on its own
+    ///
+    /// ## Rules
+    /// * Sets are made of at least 3 cards of a value other than Two or Joker
+    /// * Sets hold at least one card of their value
+    /// * Two's may only complete a set, never add to a complete one
+    pub fn is_set(&self) -> bool {
+        match self.value {
+            CardValue::Two | CardValue::Joker => false,
+            _ => {
+                self.is_consistent()
+                    && self.naturals() > 0
+                    && self.cards.len() >= SET_SIZE
+                    && self.wilds() <= SET_SIZE.saturating_sub(self.naturals())
+            }
+        }
+    }
+}
+
+/// Limits on the wild Two's laid down with a value
+///
+/// ## Rules
+/// Limits apply to the set as a whole, including the cards the team
+/// already laid down for its value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WildCardPolicy {
+    /// Most Two's a set may hold, None for no limit
+    pub max_wilds_per_meld: Option<usize>,
+    /// Whether a set may hold more Two's than cards of its value
+    pub wilds_may_outnumber_naturals: bool,
+    /// Whether Two's may be laid down on their own
+    pub allow_pure_wild_melds: bool,
+}
+
+impl Default for WildCardPolicy {
+    fn default() -> Self {
+        Self {
+            max_wilds_per_meld: Some(2),
+            wilds_may_outnumber_naturals: false,
+            allow_pure_wild_melds: false,
+        }
+    }
+}
+
+/// What a team's first opening has to hold, the partner of a player
+/// who opened always opens with a single set
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpeningRule {
+    /// At least 3 complete sets
+    Sets,
+    /// At least 1 complete set, worth at least the threshold
+    Points(OpeningThreshold),
+    /// At least 3 complete sets, worth at least the threshold
+    SetsAndPoints(OpeningThreshold),
+}
+
+impl Default for OpeningRule {
+    fn default() -> Self {
+        OpeningRule::Sets
+    }
+}
+
+/// Points an opening meld has to be worth, as counted by `PlayerMove::card_points`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OpeningThreshold {
+    pub minimum_points: u16,
+    /// The threshold rises by `raise_by` for every `raise_every` overall
+    /// points of the team, 0 keeps it fixed
+    pub raise_every: u16,
+    pub raise_by: u16,
+}
+
+impl OpeningThreshold {
+    /// Returns the points the opening meld of a team has to be worth
+    ///
+    /// ## Arguments
+    /// `overall_points` - The points of the team from the previous rounds
+    pub fn for_team(&self, overall_points: i16) -> u16 {
+        if self.raise_every == 0 || overall_points <= 0 {
+            return self.minimum_points;
+        }
+        let raises = overall_points as u16 / self.raise_every;
+        self.minimum_points
+            .saturating_add(raises.saturating_mul(self.raise_by))
+    }
+}
+
+/// How the Queen of Spades is melded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueenOfSpadesMeld {
+    /// Along with the other Queens
+    AsQueen,
+    /// Only on its own, apart from the other Queens
+    Standalone,
+}
+
+/// Rules specific to the Queen of Spades
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueenOfSpadesRules {
+    pub meld: QueenOfSpadesMeld,
+    /// Whether the discard pile can be picked up with the Queen of Spades on top
+    pub pick_up_from_discard: bool,
+    /// Points a team loses for every Queen of Spades left in the hands of its
+    /// players at the end of the round, on top of the points of the card
+    pub holding_penalty: u16,
+}
+
+impl Default for QueenOfSpadesRules {
+    fn default() -> Self {
+        Self {
+            meld: QueenOfSpadesMeld::AsQueen,
+            pick_up_from_discard: true,
+            holding_penalty: 0,
+        }
+    }
+}
+
+impl QueenOfSpadesRules {
+    /// Sets apart the Queens of Spades of the groups when they are melded on their own
+    ///
+    /// ## Rules
+    /// * When melded as a Queen, the groups are left as they are
+    /// * When standalone, a group holding a Queen of Spades may only hold Queens of Spades
+    ///
+    /// ## Returns
+    /// The Queens of Spades set apart along with the other groups,
+    /// None if a group mixes the Queen of Spades with other cards
+    pub fn split_groups(&self, groups: &[MeldGroup]) -> Option<(Vec<Card>, Vec<MeldGroup>)> {
+        if self.meld == QueenOfSpadesMeld::AsQueen {
+            return Some((vec![], groups.to_vec()));
+        }
+        let mut queens = vec![];
+        let mut others = vec![];
+        for group in groups {
+            let held = group
+                .cards
+                .iter()
+                .filter(|card| is_queen_of_spades(card))
+                .count();
+            if held == 0 {
+                others.push(group.clone());
+            } else if held == group.cards.len() {
+                queens.extend(group.cards.iter().cloned());
+            } else {
+                return None;
+            }
+        }
+        Some((queens, others))
+    }
+
+    /// Sets apart the Queens of Spades of loose cards when they are melded on their own
+    ///
+    /// ## Returns
+    /// The Queens of Spades set apart along with the other cards
+    pub fn split_cards(&self, cards: Vec<Card>) -> (Vec<Card>, Vec<Card>) {
+        match self.meld {
+            QueenOfSpadesMeld::AsQueen => (vec![], cards),
+            QueenOfSpadesMeld::Standalone => cards.into_iter().partition(is_queen_of_spades),
+        }
+    }
+}
+
+/// Returns whether the card is a Two or a Joker
+fn is_wild(card: &Card) -> bool {
+    matches!(card.value, CardValue::Two | CardValue::Joker)
+}
+
+/// Returns whether the card is the Queen of Spades
+pub fn is_queen_of_spades(card: &Card) -> bool {
+    card.value == CardValue::Queen && matches!(card.suit, CardSuit::Spades)
+}
+
+/// Points of every team at the end of a round
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundSummary {
+    /// The teams in the order of `DDPState::partners`
+    pub teams: Vec<TeamRoundSummary>,
+    /// The Queen of Spades rules the round was played with
+    pub queen_of_spades: QueenOfSpadesRules,
+}
+
+/// Points of a team at the end of a round
+#[derive(Clone, Debug, PartialEq)]
+pub struct TeamRoundSummary {
+    /// Points laid down by the team
+    pub melded_points: u16,
+    /// Points of the cards left in the hands of the players
+    pub hand_points: u16,
+    /// Seats of the players caught holding the Queen of Spades
+    pub caught_with_queen_of_spades: Vec<usize>,
+    /// Points lost for holding the Queen of Spades, see `QueenOfSpadesRules::holding_penalty`
+    pub queen_of_spades_penalty: u16,
+}
+
+impl TeamRoundSummary {
+    /// Returns the points the team scored over the round
+    pub fn total(&self) -> i16 {
+        self.melded_points as i16 - self.hand_points as i16 - self.queen_of_spades_penalty as i16
+    }
+}
+
+/// What the last `Open` or `AddPoints` of the turn changed, kept to undo it
+#[derive(Clone, Debug)]
+pub struct UndoPoint {
+    /// The player who laid the cards down
+    pub player: usize,
+    /// The hand of the player before the cards were laid down
+    pub hand: Vec<Card>,
+    /// The teams before the cards were laid down
+    pub partners: Vec<Partners>,
+    /// The identifiers of the cards before they were laid down
+    pub card_ids: CardIds,
+}
+
+impl UndoPoint {
+    /// Saves what a meld of the player whose turn it is would change,
+    /// the cards set aside for it are saved back in the hand
+    fn of(game: &DDPState) -> Self {
+        let player = game.default_state.turn;
+        let mut players = game.default_state.players.clone();
+        let mut card_ids = game.card_ids.clone();
+        card_ids.put_back(&mut players);
+        Self {
+            player,
+            hand: players.swap_remove(player).hand,
+            partners: game.partners.clone(),
+            card_ids,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct DDPState {
     pub default_state: GameState,
     pub partners: Vec<Partners>,
+    pub wild_cards: WildCardPolicy,
+    pub opening: OpeningRule,
+    pub queen_of_spades: QueenOfSpadesRules,
+    /// Whether a wild card was discarded since the discard pile was last picked up
+    pub discard_pile_frozen: bool,
+    /// The summary of the last round played, set when the round ends
+    pub last_round: Option<RoundSummary>,
+    /// The last meld of the turn, until another move is played
+    pub undo_point: Option<UndoPoint>,
+    /// Identifiers of the cards wherever they are on the table, see `CardIds`
+    pub card_ids: CardIds,
 }
 
 impl DDPState {
@@ -34,78 +420,143 @@ impl DDPState {
         }
         panic!()
     }
+
+    /// Returns the index of the team the player is part of
+    pub fn get_team_index(&self, player: usize) -> Option<usize> {
+        self.partners
+            .iter()
+            .position(|partner| partner.get_partner(player).is_some())
+    }
+
+    /// Returns whether the player has opened themselves this round
+    pub fn player_has_opened(&self, player: usize) -> bool {
+        match self.get_team_index(player) {
+            Some(team) => match self.partners[team].who_opened(player) {
+                WhoOpened::Me | WhoOpened::Both => true,
+                WhoOpened::Partner | WhoOpened::Nobody => false,
+            },
+            None => false,
+        }
+    }
 }
 
 pub struct DameDePiqueGameBuilder;
 
 impl PlayerMove {
-    /// Verifies that the provided hand can open
+    /// Verifies that the provided hand can open, grouping the cards
+    /// as done by `MeldGroup::group_cards`
+    pub(crate) fn hand_can_open(
+        who_opened: WhoOpened,
+        hand: &[Card],
+        policy: &WildCardPolicy,
+    ) -> bool {
+        Self::groups_can_open(
+            who_opened,
+            &MeldGroup::group_cards(hand, &HashMap::new()),
+            policy,
+        )
+    }
+
+    /// Verifies that the provided groups can open
     ///
     /// ## Rules
     /// * If neither player on the team has opened,
-    ///     3 sets of 3 cards are required to open
+    ///     at least 3 sets of 3 or more cards are required to open
     /// * If the partner has opened
-    ///     1 sets of 3 cards is required to open
+    ///     at least 1 set of 3 or more cards is required to open
+    /// * Every card other than Jokers has to be part of a set
     /// * Sets excludes Two's and Jokers
     /// * Two's are wild and can be added to an
     ///     incomplete set to complete it
+    /// * Jokers are not counted toward an opening hand
+    /// * Every set follows the wild card policy
     /// * A player may not open twice
     ///
     /// ## Arguments
     /// `who_opened` - An enum representing which player(s) on the
     ///                 team have opened
-    /// `hand` - The cards being verified
+    /// `groups` - The cards being verified, as grouped by the player
+    /// `policy` - The limits on wild cards
     ///
     /// ## Returns
-    /// A boolean of whether the hand can open or not
-    fn hand_can_open(who_opened: WhoOpened, hand: &[Card]) -> bool {
-        let mut cards: HashMap<CardValue, usize> = HashMap::new();
-        for card in hand.iter() {
-            match cards.get_mut(&card.value) {
-                Some(v) => *v += 1,
-                None => {
-                    // Jokers are not counted toward an opening hand
-                    if card.value != CardValue::Joker {
-                        cards.insert(card.value, 1);
-                    }
-                }
-            }
-        }
+    /// A boolean of whether the groups can open or not
+    fn groups_can_open(
+        who_opened: WhoOpened,
+        groups: &[MeldGroup],
+        policy: &WildCardPolicy,
+    ) -> bool {
+        let sets_required = match who_opened {
+            WhoOpened::Both | WhoOpened::Me => return false,
+            WhoOpened::Partner => PARTNER_OPENED_SETS,
+            WhoOpened::Nobody => OPENING_SETS,
+        };
+        Self::opening_sets(groups, policy).map_or(false, |sets| sets >= sets_required)
+    }
 
-        // Two's are not counted toward sets of 3 but are considered wild
-        let mut twos = match cards.remove(&CardValue::Two) {
-            Some(n) => n,
-            None => 0,
-        } as i32;
-
-        // Complete sets
-        let triples = cards.iter().filter(|(_, &v)| v == 3).count();
-        // Sets of 2 (assuming one wild)
-        let doubles = cards.iter().filter(|(_, &v)| v == 2).count();
-        // Sets of 1 (assuming two wilds)
-        let singles = cards.iter().filter(|(_, &v)| v == 1).count();
-
-        match who_opened {
-            WhoOpened::Both | WhoOpened::Me => false,
-            WhoOpened::Partner => {
-                // Verifying that the amount of Two's provided complete the set
-                // in the provided hand
-                twos -= (singles * 2) as i32;
-                if twos != (doubles as i32) {
-                    return false;
-                }
-                singles + doubles + triples == 1
+    /// Counts the sets of an opening meld
+    ///
+    /// ## Returns
+    /// The amount of sets, None if a group is neither a set nor Jokers
+    fn opening_sets(groups: &[MeldGroup], policy: &WildCardPolicy) -> Option<usize> {
+        let mut set_values = HashSet::new();
+        for group in groups {
+            if group.value == CardValue::Joker && group.is_consistent() {
+                continue;
             }
-            WhoOpened::Nobody => {
-                // Verifying that the amount of Two's provided complete every set
-                // in the provided hand
-                twos -= (singles * 2) as i32;
-                if twos != (doubles as i32) {
-                    return false;
-                }
-                singles + doubles + triples == 3
+            // Every other group must be a set of its own value
+            if !group.is_set() || !group.respects(policy, &[]) || !set_values.insert(group.value) {
+                return None;
             }
         }
+        Some(set_values.len())
+    }
+
+    /// Verifies that the groups can open under the opening rule of the game
+    ///
+    /// ## Rules
+    /// * The partner of a player who opened opens as with `groups_can_open`
+    /// * The first opening of a team follows `DDPState::opening`, its threshold
+    ///     rising with the team's overall points
+    ///
+    /// ## Arguments
+    /// `game` - The state of the game
+    /// `turn` - The player opening
+    /// `groups` - The cards being verified, as grouped by the player
+    pub(crate) fn opening_is_valid(game: &DDPState, turn: usize, groups: &[MeldGroup]) -> bool {
+        let policy = game.wild_cards;
+        let opening = game.opening;
+        let partners = match game.get_team_index(turn) {
+            Some(team) => &game.partners[team],
+            None => return false,
+        };
+        let overall_points = partners.overall_points;
+        let (sets_required, threshold) = match (partners.who_opened(turn), opening) {
+            (WhoOpened::Nobody, OpeningRule::Points(threshold)) => (PARTNER_OPENED_SETS, threshold),
+            (WhoOpened::Nobody, OpeningRule::SetsAndPoints(threshold)) => (OPENING_SETS, threshold),
+            (who_opened, _) => return Self::groups_can_open(who_opened, groups, &policy),
+        };
+
+        let points: u16 = groups
+            .iter()
+            .flat_map(|group| group.cards.iter())
+            .map(Self::card_points)
+            .sum();
+        Self::opening_sets(groups, &policy).map_or(false, |sets| sets >= sets_required)
+            && points >= threshold.for_team(overall_points)
+    }
+
+    /// Returns whether the cards hold a natural pair matching the top card of the discard pile
+    ///
+    /// ## Rules
+    /// A frozen discard pile can only be picked up with 2 cards of the top card's value,
+    /// Two's don't stand in for them
+    pub(crate) fn holds_natural_pair(cards: &[Card], top_card: &Card) -> bool {
+        !is_wild(top_card)
+            && cards
+                .iter()
+                .filter(|card| card.value == top_card.value)
+                .count()
+                >= 2
     }
 
     fn can_add_points(turn: usize, game: &mut DDPState) -> bool {
@@ -117,88 +568,162 @@ impl PlayerMove {
 
     /// Returns whether the top card is even a card that is allowed to be picked up
     /// e.g. Not a 2, Joker or a card in the player's shared points
-    fn player_can_pickup_top_discard(turn: usize, game: &mut DDPState) -> bool {
-        let top_card = game.default_state.deck.peek_top_discarded_card().cloned();
-        let who_opened = &game.get_partners_from_player(turn).who_opened(turn);
-        let points_deck = &game.get_partners_from_player(turn).points_deck;
+    ///
+    /// ## Rules
+    /// The Queen of Spades may only be picked up when the rules allow it and
+    /// it is melded along with the other Queens
+    pub(crate) fn player_can_pickup_top_discard(turn: usize, game: &DDPState) -> bool {
+        let top_card = game.default_state.deck.peek_top_discarded_card();
+        let queen_of_spades = game.queen_of_spades;
+        let partners = match game.get_team_index(turn) {
+            Some(team) => &game.partners[team],
+            None => return false,
+        };
+        let who_opened = &partners.who_opened(turn);
+        let points_deck = &partners.points_deck;
 
         if let Some(top_card) = top_card {
-            let can_pickup = top_card.value != CardValue::Joker
+            top_card.value != CardValue::Joker
                 && top_card.value != CardValue::Two
+                && (!is_queen_of_spades(&top_card)
+                    || (queen_of_spades.pick_up_from_discard
+                        && queen_of_spades.meld == QueenOfSpadesMeld::AsQueen))
                 && (!points_deck.contains_key(&top_card.value)
                     || match who_opened {
                         WhoOpened::Nobody | WhoOpened::Partner => true,
                         _ => false,
-                    });
-            println!(
-                "The player is allowed to pickup the top discard: {}",
-                can_pickup
-            );
-            can_pickup
+                    })
         } else {
             false
         }
     }
 }
 
-impl GameBuilder for DameDePiqueGameBuilder {
-    type E = DameDePiqueError;
-    type S = DDPState;
-    fn initialize_game() -> Result<Self::S, Self::E> {
-        let mut deck = Deck::new(DeckType::WithJokers);
-        deck.extend(Deck::new(DeckType::WithJokers));
-        deck.shuffle();
+impl DameDePiqueGameBuilder {
+    /// Deals a new game where each seat is named after the provided players
+    ///
+    /// ## Arguments
+    /// `player_names` - The names of the players in seat order, seats without
+    ///                  a name are given the default `Player {seat}` name
+    ///
+    /// ## Returns
+    /// The initial state of the game
+    pub fn initialize_game_with_players(
+        player_names: &[String],
+    ) -> Result<DDPState, DameDePiqueError> {
+        let (mut deck, stock) = shoe();
+        let mut card_ids = CardIds::new(stock, PLAYER_COUNT, 2);
 
         let mut players = Vec::new();
 
-        for i in 0..4 {
-            players.push(Player::new(format!("Player {}", i), deck.draw_cards(13)?));
+        for i in 0..PLAYER_COUNT {
+            let name = match player_names.get(i) {
+                Some(name) => name.clone(),
+                None => format!("Player {}", i),
+            };
+            // Cards are dealt one at a time so their identifiers follow them
+            let mut hand = Vec::with_capacity(13);
+            while hand.len() < 13 {
+                match deck.draw_card() {
+                    Some(card) => hand.push(card),
+                    None => return Err(DameDePiqueError::IncorrectCardNumberRequest),
+                }
+                card_ids.draw(i);
+            }
+            players.push(Player::new(name, hand));
         }
 
+        // A wild card turned over to start the discard pile freezes it
+        let mut discard_pile_frozen = false;
         if let Some(top_card) = deck.draw_card() {
+            discard_pile_frozen = is_wild(&top_card);
             deck.discard_card(top_card);
+            card_ids.turn_over_top_card();
         }
 
-        let state = DDPState {
+        Ok(DDPState {
             default_state: GameState {
                 players,
                 deck,
                 turn: 0,
             },
             partners: vec![Partners::new(0, 2), Partners::new(1, 3)],
-        };
-        Ok(state)
+            wild_cards: WildCardPolicy::default(),
+            opening: OpeningRule::default(),
+            queen_of_spades: QueenOfSpadesRules::default(),
+            discard_pile_frozen,
+            last_round: None,
+            undo_point: None,
+            card_ids,
+        })
+    }
+}
+
+impl GameBuilder for DameDePiqueGameBuilder {
+    type E = DameDePiqueError;
+    type S = DDPState;
+    fn initialize_game() -> Result<Self::S, Self::E> {
+        Self::initialize_game_with_players(&[])
     }
 }
 
 impl GameRules<DDPState, DameDePiqueError> for PlayerMove {
     fn handle_move(&self, game: &mut DDPState) -> Result<GameStatus, DameDePiqueError> {
+        // Only the last meld of the turn can be undone, any other move
+        // played successfully forgets it
+        let undo_point = match self {
+            PlayerMove::Open(_) | PlayerMove::AddPoints(_) => Some(UndoPoint::of(game)),
+            _ => None,
+        };
         match self {
             PlayerMove::Draw => {
+                let turn = game.default_state.turn;
+                let restocked = game.card_ids.stock.is_empty();
                 if let Err(e) =
                     DefaultMove::handle_move(&DefaultMove::Draw, &mut game.default_state)
                 {
                     return Err(e.into());
                 }
+                if restocked {
+                    if let Some(drawn) = game.default_state.players[turn].hand.last() {
+                        game.card_ids
+                            .turn_over_discard_pile(&game.default_state.deck, turn, drawn);
+                    }
+                } else {
+                    game.card_ids.draw(turn);
+                }
             }
             PlayerMove::Discard(c) => {
+                let turn = game.default_state.turn;
+                let wild = match game.default_state.players[turn].hand.get(*c) {
+                    Some(card) => is_wild(card),
+                    None => return Err(DameDePiqueError::IncorrectCardNumberRequest),
+                };
                 if let Err(e) =
                     DefaultMove::handle_move(&DefaultMove::Discard(*c), &mut game.default_state)
                 {
                     return Err(e.into());
                 }
+                game.card_ids.discard(turn, *c);
+                // Discarding a wild card freezes the pile until it is picked up
+                if wild {
+                    game.discard_pile_frozen = true;
+                }
             }
             PlayerMove::TakeDiscardPile(cards) => {
                 // Rules: A user can pickup the discard pile at all times,
                 // If the user hasn't opened, then the restrictions
                 let turn = game.default_state.turn;
-                let mut cards = cards.clone();
+                let policy = game.wild_cards;
+                // Queens of Spades melded on their own don't count toward the sets
+                let (queens, mut cards) = game.queen_of_spades.split_cards(cards.clone());
 
                 // Verification that the player can't open before adding the top discarded
                 // card
-                if PlayerMove::hand_can_open(
-                    game.get_partners_from_player(turn).who_opened(turn),
-                    &cards,
+                if PlayerMove::opening_is_valid(
+                    game,
+                    turn,
+                    &MeldGroup::group_cards(&cards, &HashMap::new()),
                 ) || !PlayerMove::player_can_pickup_top_discard(turn, game)
                 {
                     println!(
@@ -210,13 +735,18 @@ impl GameRules<DDPState, DameDePiqueError> for PlayerMove {
                 }
 
                 if let Some(card) = game.default_state.deck.peek_top_discarded_card() {
+                    if game.discard_pile_frozen && !PlayerMove::holds_natural_pair(&cards, card) {
+                        println!("The discard pile is frozen, a natural pair is required.");
+                        return Err(DameDePiqueError::InvalidDiscardOpeningHand(turn));
+                    }
                     cards.push(card.clone());
                 }
                 match game.get_partners_from_player(turn).who_opened(turn) {
                     WhoOpened::Nobody | WhoOpened::Partner => {
-                        if !PlayerMove::hand_can_open(
-                            game.get_partners_from_player(turn).who_opened(turn),
-                            &cards,
+                        if !PlayerMove::opening_is_valid(
+                            game,
+                            turn,
+                            &MeldGroup::group_cards(&cards, &HashMap::new()),
                         ) {
                             println!(
                                 "The hand wasn't valid to open with, {:?} : {:?}",
@@ -229,7 +759,7 @@ impl GameRules<DDPState, DameDePiqueError> for PlayerMove {
                         }
                     }
                     _ => {
-                        if !PlayerMove::hand_can_open(WhoOpened::Partner, &cards) {
+                        if !PlayerMove::hand_can_open(WhoOpened::Partner, &cards, &policy) {
                             println!("The hand wasn't valid to pickup deck with, {:?}", &cards);
                             return Err(DameDePiqueError::InvalidDiscardOpeningHand(
                                 game.default_state.turn,
@@ -239,67 +769,99 @@ impl GameRules<DDPState, DameDePiqueError> for PlayerMove {
                 }
                 // Valid Opening hand to pickup the discard pile so we remove the top card of the dicard pile
                 game.default_state.deck.pop_top_discarded_card();
+                let top_card = game.card_ids.discard_pile.pop();
 
                 // Opening action
+                let groups = MeldGroup::group_cards(&cards, &HashMap::new());
+                if let Some(team) = game.get_team_index(turn) {
+                    game.card_ids.lay_down(team, &groups, &queens, top_card);
+                }
+                game.get_partners_from_player(turn).add_points(groups);
                 game.get_partners_from_player(turn)
-                    .add_points(cards.to_vec());
+                    .queens_of_spades
+                    .extend(queens);
                 game.get_partners_from_player(turn).update_status(turn);
 
                 // Adding cards from discard pile to hand
                 for card in game.default_state.deck.take_discard_pile().drain(..) {
                     game.default_state.players[game.default_state.turn].add_card_to_hand(card);
                 }
+                game.card_ids.pick_up_discard_pile(turn);
+                game.discard_pile_frozen = false;
             }
-            PlayerMove::Open(cards) => {
+            PlayerMove::Open(groups) => {
                 let turn = game.default_state.turn;
-                if !PlayerMove::hand_can_open(
-                    game.get_partners_from_player(turn).who_opened(turn),
-                    &cards,
-                ) {
+                let (queens, groups) = match game.queen_of_spades.split_groups(groups) {
+                    Some(split) => split,
+                    None => return Err(DameDePiqueError::InvalidOpeningHand(turn)),
+                };
+                if !PlayerMove::opening_is_valid(game, turn, &groups) {
                     return Err(DameDePiqueError::InvalidOpeningHand(
                         game.default_state.turn,
                     ));
                 }
+                if let Some(team) = game.get_team_index(turn) {
+                    game.card_ids.lay_down(team, &groups, &queens, None);
+                }
+                game.get_partners_from_player(turn).add_points(groups);
                 game.get_partners_from_player(turn)
-                    .add_points(cards.to_vec());
+                    .queens_of_spades
+                    .extend(queens);
                 game.get_partners_from_player(turn).update_status(turn);
             }
-            PlayerMove::AddPoints(cards) => {
+            PlayerMove::AddPoints(groups) => {
                 let turn = game.default_state.turn;
                 if !PlayerMove::can_add_points(turn, game) {
                     return Err(DameDePiqueError::PlayerCantAddPoints(
                         game.default_state.turn,
                     ));
                 }
+                let policy = game.wild_cards;
+                let (queens, groups) = match game.queen_of_spades.split_groups(groups) {
+                    Some(split) => split,
+                    None => return Err(DameDePiqueError::InvalidPoints),
+                };
                 if !game
                     .get_partners_from_player(game.default_state.turn)
-                    .are_valid_points(&cards)
+                    .are_valid_groups(&groups, &policy)
                 {
                     return Err(DameDePiqueError::InvalidPoints);
                 }
+                if let Some(team) = game.get_team_index(turn) {
+                    game.card_ids.lay_down(team, &groups, &queens, None);
+                }
                 game.get_partners_from_player(game.default_state.turn)
-                    .add_points(cards.to_vec());
+                    .add_points(groups);
+                game.get_partners_from_player(turn)
+                    .queens_of_spades
+                    .extend(queens);
+            }
+            PlayerMove::Undo => {
+                let turn = game.default_state.turn;
+                match game.undo_point.take() {
+                    Some(point) if point.player == turn => {
+                        game.default_state.players[turn].hand = point.hand;
+                        game.partners = point.partners;
+                        game.card_ids = point.card_ids;
+                    }
+                    _ => return Err(DameDePiqueError::NothingToUndo(turn)),
+                }
             }
         }
+        game.undo_point = undo_point;
 
+        // A meld ending the round can't be taken back once the round is scored
+        if Self::is_round_over(game) {
+            game.undo_point = None;
+        }
         if Self::is_game_over(game) && Self::is_round_over(game) {
             Ok(GameStatus::GameOver)
         } else if Self::is_round_over(game) {
-            for mut partner in game.partners.iter_mut() {
-                partner.overall_points += partner.get_points_total() as i16;
-                let player_1_points = Self::calculate_point_total(
-                    game.default_state.players[partner.player_a_index]
-                        .hand
-                        .clone(),
-                );
-                let player_2_points = Self::calculate_point_total(
-                    game.default_state.players[partner.player_b_index]
-                        .hand
-                        .clone(),
-                );
-                partner.overall_points -= player_1_points as i16;
-                partner.overall_points -= player_2_points as i16;
+            let summary = Self::round_summary(game);
+            for (partner, team) in game.partners.iter_mut().zip(summary.teams.iter()) {
+                partner.overall_points += team.total();
             }
+            game.last_round = Some(summary);
 
             Ok(GameStatus::RoundOver)
         } else {
@@ -326,46 +888,118 @@ impl GameRules<DDPState, DameDePiqueError> for PlayerMove {
     }
 
     fn end_turn(state: &mut DDPState) {
+        state.undo_point = None;
         DefaultMove::end_turn(&mut state.default_state);
     }
 }
 
 impl PlayerMove {
+    /// Counts the points of every team at the end of the round
+    ///
+    /// ## Rules
+    /// * Teams score the points they laid down
+    /// * Teams lose the points of the cards left in the hands of their players
+    /// * Teams lose the holding penalty for every Queen of Spades left in those hands
+    pub fn round_summary(game: &DDPState) -> RoundSummary {
+        let rules = game.queen_of_spades;
+        let players = &game.default_state.players;
+        let teams = game
+            .partners
+            .iter()
+            .map(|partner| {
+                let seats = [partner.player_a_index, partner.player_b_index];
+                let hand_points = seats
+                    .iter()
+                    .map(|&seat| Self::calculate_point_total(players[seat].hand.clone()))
+                    .sum();
+                let held = seats
+                    .iter()
+                    .map(|&seat| {
+                        players[seat]
+                            .hand
+                            .iter()
+                            .filter(|card| is_queen_of_spades(card))
+                            .count() as u16
+                    })
+                    .sum::<u16>();
+                TeamRoundSummary {
+                    melded_points: partner.get_points_total(),
+                    hand_points,
+                    caught_with_queen_of_spades: seats
+                        .iter()
+                        .cloned()
+                        .filter(|&seat| players[seat].hand.iter().any(is_queen_of_spades))
+                        .collect(),
+                    queen_of_spades_penalty: held.saturating_mul(rules.holding_penalty),
+                }
+            })
+            .collect();
+        RoundSummary {
+            teams,
+            queen_of_spades: rules,
+        }
+    }
+
     pub fn calculate_point_total(hand: Vec<Card>) -> u16 {
         let mut total = 0;
         for v in hand.iter() {
-            total += match v.value {
-                CardValue::Ace => 15,
-                CardValue::Two => 20,
-                CardValue::Three
-                | CardValue::Four
-                | CardValue::Five
-                | CardValue::Six
-                | CardValue::Seven
-                | CardValue::Eight
-                | CardValue::Nine => 5,
-                CardValue::Ten | CardValue::Jack | CardValue::King => 10,
-                CardValue::Queen => match v.suit {
-                    CardSuit::Clubs | CardSuit::Hearts | CardSuit::Diamonds => 10,
-                    CardSuit::Spades => 100,
-                    _ => 0,
-                },
-                CardValue::Joker => 50,
-            };
+            total += Self::card_points(v);
         }
         total
     }
+
+    /// Returns the amount of points a single card is worth
+    pub fn card_points(card: &Card) -> u16 {
+        match card.value {
+            CardValue::Ace => 15,
+            CardValue::Two => 20,
+            CardValue::Three
+            | CardValue::Four
+            | CardValue::Five
+            | CardValue::Six
+            | CardValue::Seven
+            | CardValue::Eight
+            | CardValue::Nine => 5,
+            CardValue::Ten | CardValue::Jack | CardValue::King => 10,
+            CardValue::Queen => match card.suit {
+                CardSuit::Clubs | CardSuit::Hearts | CardSuit::Diamonds => 10,
+                CardSuit::Spades => 100,
+                _ => 0,
+            },
+            CardValue::Joker => 50,
+        }
+    }
+
+    /// Returns the index of the card that would cost the most points
+    /// if it were still in the hand at the end of the round
+    ///
+    /// ## Arguments
+    /// `hand` - The cards of the player
+    ///
+    /// ## Returns
+    /// The index of the card, None if the hand is empty
+    pub fn highest_debt_card_index(hand: &[Card]) -> Option<usize> {
+        hand.iter()
+            .enumerate()
+            .max_by_key(|(_, card)| Self::card_points(card))
+            .map(|(i, _)| i)
+    }
 }
 
 mod tests {
     #[allow(unused_imports)]
-    use crate::gameplay::PlayerMove;
+    use crate::gameplay::{
+        DameDePiqueGameBuilder, MeldGroup, OpeningRule, OpeningThreshold, PlayerMove,
+        QueenOfSpadesMeld, QueenOfSpadesRules, WildCardPolicy,
+    };
     #[allow(unused_imports)]
     use crate::partners::WhoOpened;
     #[allow(unused_imports)]
     use card_game_engine::builder::GameBuilder;
     #[allow(unused_imports)]
     use card_game_engine::models::deck::{Card, CardSuit, CardValue};
+    #[allow(unused_imports)]
+    use card_game_engine::rules::GameRules;
 
     #[test]
     fn hand_open_before_parner() {
@@ -377,7 +1011,11 @@ mod tests {
             3
         ];
 
-        assert!(!PlayerMove::hand_can_open(WhoOpened::Nobody, &hand));
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Nobody,
+            &hand,
+            &WildCardPolicy::default()
+        ));
 
         hand.extend_from_slice(&vec![
             Card {
@@ -394,7 +1032,11 @@ mod tests {
             3
         ]);
 
-        assert!(PlayerMove::hand_can_open(WhoOpened::Nobody, &hand));
+        assert!(PlayerMove::hand_can_open(
+            WhoOpened::Nobody,
+            &hand,
+            &WildCardPolicy::default()
+        ));
 
         // Remove eights
         hand.pop();
@@ -411,7 +1053,11 @@ mod tests {
         ]);
 
         // Invalid opening hand, 3 twos must be independently
-        assert!(!PlayerMove::hand_can_open(WhoOpened::Nobody, &hand));
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Nobody,
+            &hand,
+            &WildCardPolicy::default()
+        ));
 
         hand.pop();
         hand.pop();
@@ -425,7 +1071,11 @@ mod tests {
         });
 
         // Invalid opening hand including
-        assert!(!PlayerMove::hand_can_open(WhoOpened::Nobody, &hand));
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Nobody,
+            &hand,
+            &WildCardPolicy::default()
+        ));
 
         hand.clear();
         hand.extend_from_slice(&vec![
@@ -465,7 +1115,21 @@ mod tests {
                 suit: CardSuit::Diamonds,
             },
         ];
-        assert!(PlayerMove::hand_can_open(WhoOpened::Partner, &hand));
+        // Wilds may only outnumber the naturals when the policy allows it
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Partner,
+            &hand,
+            &WildCardPolicy::default()
+        ));
+        let lenient = WildCardPolicy {
+            wilds_may_outnumber_naturals: true,
+            ..WildCardPolicy::default()
+        };
+        assert!(PlayerMove::hand_can_open(
+            WhoOpened::Partner,
+            &hand,
+            &lenient
+        ));
 
         hand.clear();
         hand.extend_from_slice(&[
@@ -482,7 +1146,11 @@ mod tests {
                 suit: CardSuit::Diamonds,
             },
         ]);
-        assert!(PlayerMove::hand_can_open(WhoOpened::Partner, &hand));
+        assert!(PlayerMove::hand_can_open(
+            WhoOpened::Partner,
+            &hand,
+            &WildCardPolicy::default()
+        ));
 
         hand.clear();
         hand.extend_from_slice(&[
@@ -499,7 +1167,11 @@ mod tests {
                 suit: CardSuit::Diamonds,
             },
         ]);
-        assert!(!PlayerMove::hand_can_open(WhoOpened::Partner, &hand));
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Partner,
+            &hand,
+            &WildCardPolicy::default()
+        ));
     }
 
     #[test]
@@ -544,10 +1216,495 @@ mod tests {
             },
         ];
 
-        assert!(PlayerMove::hand_can_open(WhoOpened::Nobody, &hand));
+        assert!(PlayerMove::hand_can_open(
+            WhoOpened::Nobody,
+            &hand,
+            &WildCardPolicy::default()
+        ));
 
         // A player cannot open twice
-        assert!(!PlayerMove::hand_can_open(WhoOpened::Both, &hand));
-        assert!(!PlayerMove::hand_can_open(WhoOpened::Me, &hand));
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Both,
+            &hand,
+            &WildCardPolicy::default()
+        ));
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Me,
+            &hand,
+            &WildCardPolicy::default()
+        ));
+    }
+
+    #[test]
+    fn open_with_chosen_groups() {
+        let ace = Card {
+            value: CardValue::Ace,
+            suit: CardSuit::Clubs,
+        };
+        let king = Card {
+            value: CardValue::King,
+            suit: CardSuit::Hearts,
+        };
+        let two = Card {
+            value: CardValue::Two,
+            suit: CardSuit::Spades,
+        };
+
+        // The wild completes the set the player put it in
+        let groups = vec![MeldGroup::new(
+            CardValue::Ace,
+            vec![ace.clone(), ace.clone(), two.clone()],
+        )];
+        assert!(PlayerMove::groups_can_open(
+            WhoOpened::Partner,
+            &groups,
+            &WildCardPolicy::default()
+        ));
+
+        // Two's can't be added to a set they don't complete
+        let groups = vec![MeldGroup::new(
+            CardValue::Ace,
+            vec![ace.clone(), ace.clone(), two.clone(), two.clone()],
+        )];
+        assert!(!PlayerMove::groups_can_open(
+            WhoOpened::Partner,
+            &groups,
+            &WildCardPolicy::default()
+        ));
+
+        // Cards of another value don't belong in a set
+        let groups = vec![MeldGroup::new(
+            CardValue::Ace,
+            vec![ace.clone(), ace.clone(), king.clone()],
+        )];
+        assert!(!PlayerMove::groups_can_open(
+            WhoOpened::Partner,
+            &groups,
+            &WildCardPolicy::default()
+        ));
+
+        // Loose wild cards don't open
+        let groups = vec![
+            MeldGroup::new(CardValue::King, vec![king.clone(); 3]),
+            MeldGroup::new(CardValue::Two, vec![two.clone()]),
+        ];
+        assert!(!PlayerMove::groups_can_open(
+            WhoOpened::Partner,
+            &groups,
+            &WildCardPolicy::default()
+        ));
+
+        // A value is opened with a single set
+        let groups = vec![
+            MeldGroup::new(CardValue::King, vec![king.clone(); 3]),
+            MeldGroup::new(CardValue::King, vec![king.clone(), king.clone(), two]),
+            MeldGroup::new(CardValue::Ace, vec![ace; 3]),
+        ];
+        assert!(!PlayerMove::groups_can_open(
+            WhoOpened::Nobody,
+            &groups,
+            &WildCardPolicy::default()
+        ));
+    }
+
+    #[test]
+    fn open_with_more_than_the_minimum() {
+        let card = |value| Card {
+            value,
+            suit: CardSuit::Hearts,
+        };
+        let policy = WildCardPolicy::default();
+
+        // Four sets
+        let mut hand = Vec::new();
+        for value in &[
+            CardValue::Three,
+            CardValue::Six,
+            CardValue::Nine,
+            CardValue::King,
+        ] {
+            hand.extend(vec![card(*value); 3]);
+        }
+        assert!(PlayerMove::hand_can_open(WhoOpened::Nobody, &hand, &policy));
+
+        // A four-of-a-kind is a set, Jokers come along
+        hand.push(card(CardValue::King));
+        hand.push(card(CardValue::Joker));
+        assert!(PlayerMove::hand_can_open(WhoOpened::Nobody, &hand, &policy));
+        assert!(PlayerMove::hand_can_open(
+            WhoOpened::Partner,
+            &hand,
+            &policy
+        ));
+
+        // Stray cards can't come along
+        hand.push(card(CardValue::Ace));
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Nobody,
+            &hand,
+            &policy
+        ));
+
+        // Still not enough sets
+        let hand = vec![card(CardValue::Four); 5];
+        assert!(!PlayerMove::hand_can_open(
+            WhoOpened::Nobody,
+            &hand,
+            &policy
+        ));
+        assert!(PlayerMove::hand_can_open(
+            WhoOpened::Partner,
+            &hand,
+            &policy
+        ));
+    }
+
+    #[test]
+    fn open_with_minimum_points() {
+        let card = |value| Card {
+            value,
+            suit: CardSuit::Diamonds,
+        };
+        let set = |value| MeldGroup::new(value, vec![card(value); 3]);
+        let jokers = MeldGroup::new(CardValue::Joker, vec![card(CardValue::Joker)]);
+        let threshold = OpeningThreshold {
+            minimum_points: 50,
+            raise_every: 500,
+            raise_by: 25,
+        };
+        let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+        game.opening = OpeningRule::Points(threshold);
+
+        // 30 points
+        assert!(!PlayerMove::opening_is_valid(
+            &game,
+            0,
+            &[set(CardValue::King)]
+        ));
+        // 95 points
+        let meld = [set(CardValue::Ace), jokers.clone()];
+        assert!(PlayerMove::opening_is_valid(&game, 0, &meld));
+
+        // The threshold rises to 100 points once the team has 1000 points
+        game.partners[0].overall_points = 1000;
+        assert_eq!(threshold.for_team(1000), 100);
+        assert!(!PlayerMove::opening_is_valid(&game, 0, &meld));
+        game.partners[0].overall_points = -200;
+        assert_eq!(threshold.for_team(-200), 50);
+
+        // Both the sets and the points are required
+        game.opening = OpeningRule::SetsAndPoints(threshold);
+        let mut meld = vec![
+            set(CardValue::Three),
+            set(CardValue::Four),
+            set(CardValue::Five),
+        ];
+        assert!(!PlayerMove::opening_is_valid(&game, 0, &meld));
+        meld.push(jokers);
+        assert!(PlayerMove::opening_is_valid(&game, 0, &meld));
+
+        // The partner of a player who opened only needs a set
+        game.partners[0].update_status(2);
+        assert!(PlayerMove::opening_is_valid(
+            &game,
+            0,
+            &[set(CardValue::King)]
+        ));
+    }
+
+    #[test]
+    fn queen_of_spades_rules() {
+        let queen = |suit| Card {
+            value: CardValue::Queen,
+            suit,
+        };
+        let queens = MeldGroup::new(
+            CardValue::Queen,
+            vec![
+                queen(CardSuit::Hearts),
+                queen(CardSuit::Clubs),
+                queen(CardSuit::Spades),
+            ],
+        );
+        let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+        game.default_state
+            .deck
+            .discard_card(queen(CardSuit::Spades));
+        let mut rules = QueenOfSpadesRules::default();
+        assert!(PlayerMove::player_can_pickup_top_discard(0, &game));
+        assert_eq!(
+            rules.split_groups(&[queens.clone()]),
+            Some((vec![], vec![queens.clone()]))
+        );
+
+        rules.pick_up_from_discard = false;
+        game.queen_of_spades = rules;
+        assert!(!PlayerMove::player_can_pickup_top_discard(0, &game));
+
+        // On its own, the Queen of Spades can't join the other Queens
+        rules.pick_up_from_discard = true;
+        rules.meld = QueenOfSpadesMeld::Standalone;
+        game.queen_of_spades = rules;
+        assert!(!PlayerMove::player_can_pickup_top_discard(0, &game));
+        assert_eq!(rules.split_groups(&[queens]), None);
+        let alone = MeldGroup::new(CardValue::Queen, vec![queen(CardSuit::Spades)]);
+        let set = MeldGroup::new(CardValue::Queen, vec![queen(CardSuit::Hearts); 3]);
+        assert_eq!(
+            rules.split_groups(&[alone, set.clone()]),
+            Some((vec![queen(CardSuit::Spades)], vec![set]))
+        );
+        let (held, others) =
+            rules.split_cards(vec![queen(CardSuit::Spades), queen(CardSuit::Hearts)]);
+        assert_eq!(held, vec![queen(CardSuit::Spades)]);
+        assert_eq!(others, vec![queen(CardSuit::Hearts)]);
+    }
+
+    #[test]
+    fn undo_last_meld() {
+        let card = |value| Card {
+            value,
+            suit: CardSuit::Clubs,
+        };
+        let set = |value| MeldGroup::new(value, vec![card(value); 3]);
+        let meld = vec![
+            set(CardValue::King),
+            set(CardValue::Ace),
+            set(CardValue::Five),
+        ];
+        let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+        let mut hand: Vec<Card> = meld.iter().flat_map(|group| group.cards.clone()).collect();
+        hand.push(card(CardValue::Four));
+        game.default_state.players[0].hand = hand.clone();
+        assert!(PlayerMove::Undo.handle_move(&mut game).is_err());
+
+        PlayerMove::Open(meld.clone())
+            .handle_move(&mut game)
+            .unwrap();
+        // The cards laid down leave the hand
+        game.default_state.players[0].hand.drain(..9);
+        assert!(game.player_has_opened(0));
+
+        PlayerMove::Undo.handle_move(&mut game).unwrap();
+        assert_eq!(game.default_state.players[0].hand, hand);
+        assert!(game.partners[0].points_deck.is_empty());
+        assert!(!game.player_has_opened(0));
+        // Only the last meld is undone
+        assert!(PlayerMove::Undo.handle_move(&mut game).is_err());
+
+        // Discarding ends what can be undone
+        PlayerMove::Open(meld).handle_move(&mut game).unwrap();
+        game.default_state.players[0].hand.drain(..9);
+        game.default_state.players[0]
+            .hand
+            .push(card(CardValue::Six));
+        PlayerMove::Discard(1).handle_move(&mut game).unwrap();
+        assert!(PlayerMove::Undo.handle_move(&mut game).is_err());
+        assert!(game.player_has_opened(0));
+    }
+
+    #[test]
+    fn meld_ending_the_round_cannot_be_undone() {
+        use card_game_engine::rules::GameStatus;
+
+        let card = |value| Card {
+            value,
+            suit: CardSuit::Clubs,
+        };
+        let set = |value| MeldGroup::new(value, vec![card(value); 3]);
+        let meld = vec![
+            set(CardValue::King),
+            set(CardValue::Ace),
+            set(CardValue::Five),
+        ];
+        let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+        game.default_state.players[0].hand =
+            meld.iter().flat_map(|group| group.cards.clone()).collect();
+
+        // The server takes the cards out of the hand before laying them down
+        let indices: Vec<usize> = (0..9).collect();
+        game.remove_cards(0, &indices);
+        let status = PlayerMove::Open(meld).handle_move(&mut game).unwrap();
+        assert!(matches!(status, GameStatus::RoundOver));
+        assert!(game.last_round.is_some());
+
+        assert!(PlayerMove::Undo.handle_move(&mut game).is_err());
+        assert!(game.default_state.players[0].hand.is_empty());
+        assert!(game.player_has_opened(0));
+    }
+
+    #[test]
+    fn frozen_discard_pile() {
+        let card = |value| Card {
+            value,
+            suit: CardSuit::Hearts,
+        };
+        let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+        game.discard_pile_frozen = false;
+        game.default_state.players[0].hand = vec![
+            card(CardValue::Two),
+            card(CardValue::Seven),
+            card(CardValue::Four),
+        ];
+
+        // The wild card freezes the pile, the Seven covering it doesn't thaw it
+        PlayerMove::Discard(0).handle_move(&mut game).unwrap();
+        assert!(game.discard_pile_frozen);
+        PlayerMove::Discard(0).handle_move(&mut game).unwrap();
+        assert!(game.discard_pile_frozen);
+
+        let mut cards = vec![card(CardValue::Seven), card(CardValue::Two)];
+        cards.extend(vec![card(CardValue::King); 3]);
+        cards.extend(vec![card(CardValue::Ace); 3]);
+        assert!(PlayerMove::TakeDiscardPile(cards.clone())
+            .handle_move(&mut game)
+            .is_err());
+
+        // A natural pair of Sevens takes it
+        cards[1] = card(CardValue::Seven);
+        PlayerMove::TakeDiscardPile(cards)
+            .handle_move(&mut game)
+            .unwrap();
+        assert!(!game.discard_pile_frozen);
+        assert!(game.default_state.deck.peek_top_discarded_card().is_none());
+    }
+
+    #[test]
+    fn wild_card_policy() {
+        let card = |value| Card {
+            value,
+            suit: CardSuit::Clubs,
+        };
+        let set = |naturals: usize, wilds: usize| {
+            let mut cards = vec![card(CardValue::Seven); naturals];
+            cards.extend(vec![card(CardValue::Two); wilds]);
+            MeldGroup::new(CardValue::Seven, cards)
+        };
+        let strict = WildCardPolicy::default();
+        let single_wild = WildCardPolicy {
+            max_wilds_per_meld: Some(1),
+            ..WildCardPolicy::default()
+        };
+        let anything_goes = WildCardPolicy {
+            max_wilds_per_meld: None,
+            wilds_may_outnumber_naturals: true,
+            allow_pure_wild_melds: true,
+        };
+
+        // (policy, group, cards already laid down for the value, expected)
+        let cases = vec![
+            (strict, set(3, 0), vec![], true),
+            (strict, set(2, 1), vec![], true),
+            (strict, set(1, 2), vec![], false),
+            (anything_goes, set(1, 2), vec![], true),
+            (strict, set(0, 1), vec![card(CardValue::Seven); 3], true),
+            (
+                strict,
+                set(0, 1),
+                vec![
+                    card(CardValue::Seven),
+                    card(CardValue::Seven),
+                    card(CardValue::Two),
+                ],
+                true,
+            ),
+            (
+                single_wild,
+                set(0, 1),
+                vec![
+                    card(CardValue::Seven),
+                    card(CardValue::Seven),
+                    card(CardValue::Two),
+                ],
+                false,
+            ),
+            (strict, set(0, 3), vec![card(CardValue::Seven); 3], false),
+            (
+                anything_goes,
+                set(0, 3),
+                vec![card(CardValue::Seven); 3],
+                true,
+            ),
+            (
+                strict,
+                MeldGroup::new(CardValue::Two, vec![card(CardValue::Two); 2]),
+                vec![],
+                false,
+            ),
+            (
+                anything_goes,
+                MeldGroup::new(CardValue::Two, vec![card(CardValue::Two); 2]),
+                vec![],
+                true,
+            ),
+            (
+                strict,
+                MeldGroup::new(CardValue::Joker, vec![card(CardValue::Joker); 2]),
+                vec![],
+                true,
+            ),
+        ];
+        for (policy, group, melded, expected) in cases {
+            assert_eq!(
+                group.respects(&policy, &melded),
+                expected,
+                "{:?} with {:?} under {:?}",
+                group,
+                melded,
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn group_loose_cards() {
+        let card = |value| Card {
+            value,
+            suit: CardSuit::Diamonds,
+        };
+        let hand = vec![
+            card(CardValue::Five),
+            card(CardValue::Two),
+            card(CardValue::Jack),
+            card(CardValue::Jack),
+            card(CardValue::Two),
+            card(CardValue::Joker),
+        ];
+        let groups = MeldGroup::group_cards(&hand, &std::collections::HashMap::new());
+
+        // The set closest to complete is completed first
+        assert_eq!(groups[0].value, CardValue::Jack);
+        assert_eq!(groups[0].wilds(), 1);
+        assert_eq!(groups[1].value, CardValue::Five);
+        assert_eq!(groups[1].wilds(), 1);
+        assert_eq!(groups[2].value, CardValue::Joker);
+        assert_eq!(groups.len(), 3);
+    }
+
+    #[test]
+    fn highest_debt_card() {
+        let mut hand = vec![
+            Card {
+                value: CardValue::Five,
+                suit: CardSuit::Clubs,
+            },
+            Card {
+                value: CardValue::Joker,
+                suit: CardSuit::Red,
+            },
+            Card {
+                value: CardValue::Ace,
+                suit: CardSuit::Hearts,
+            },
+        ];
+        assert_eq!(PlayerMove::highest_debt_card_index(&hand), Some(1));
+
+        hand.push(Card {
+            value: CardValue::Queen,
+            suit: CardSuit::Spades,
+        });
+        assert_eq!(PlayerMove::highest_debt_card_index(&hand), Some(3));
+
+        hand.clear();
+        assert_eq!(PlayerMove::highest_debt_card_index(&hand), None);
     }
 }
@@ -1,2 +1,6 @@
 mod gameplay;
-pub use gameplay::{DameDePiqueGameBuilder, DDPState, PlayerMove};
+pub use gameplay::{
+    is_queen_of_spades, DDPState, DameDePiqueGameBuilder, MeldGroup, OpeningRule,
+    OpeningThreshold, PlayerMove, QueenOfSpadesMeld, QueenOfSpadesRules, RoundSummary,
+    TeamRoundSummary, UndoPoint, WildCardPolicy,
+};
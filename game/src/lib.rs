@@ -1,6 +1,8 @@
+pub mod cards;
 pub mod error;
 pub mod gameplay;
 pub mod partners;
+pub mod suggestions;
 pub mod state {
     pub use card_game_engine::state::GameState;
 }
@@ -1,5 +1,6 @@
+use crate::gameplay::{MeldGroup, WildCardPolicy};
 use card_game_engine::models::deck::{Card, CardSuit, CardValue};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Copy, Debug)]
 enum TeamOpenStatus {
@@ -22,6 +23,8 @@ pub struct Partners {
     pub player_a_index: usize,
     pub player_b_index: usize,
     pub points_deck: HashMap<CardValue, Vec<Card>>,
+    /// Queens of Spades laid down on their own, apart from the points deck
+    pub queens_of_spades: Vec<Card>,
     pub overall_points: i16,
     status: TeamOpenStatus,
 }
@@ -33,18 +36,19 @@ impl Partners {
             player_b_index,
             overall_points: 0,
             points_deck: HashMap::new(),
+            queens_of_spades: Vec::new(),
             status: TeamOpenStatus::None,
         }
     }
 
-    pub fn add_points(&mut self, cards: Vec<Card>) {
-        for card in cards {
-            match self.points_deck.get_mut(&card.value) {
-                Some(v) => v.push(card),
-                None => {
-                    self.points_deck.insert(card.value, vec![card]);
-                }
-            }
+    /// Adds the groups to the team's points, wild cards are filed
+    /// under the value of the group they joined
+    pub fn add_points(&mut self, groups: Vec<MeldGroup>) {
+        for group in groups {
+            self.points_deck
+                .entry(group.value)
+                .or_default()
+                .extend(group.cards);
         }
     }
 
@@ -96,42 +100,42 @@ impl Partners {
         }
     }
 
-    pub fn are_valid_points(&self, hand: &[Card]) -> bool {
-        let mut cards: HashMap<CardValue, usize> = HashMap::new();
-        for card in hand {
-            match cards.get_mut(&card.value) {
-                Some(v) => *v += 1,
-                None => {
-                    if card.value != CardValue::Joker {
-                        cards.insert(card.value, 1);
-                    }
-                }
-            }
-        }
-        // Two's are not counted toward sets of 3 but are considered wild
-        let mut twos = match cards.remove(&CardValue::Two) {
-            Some(n) => n,
-            None => 0,
-        } as i32;
-
-        // If the player opened, then the hand needs to be point cards
-        // meaning that the cards must either be a complete set of 3,
-        // a Joker, a Two, or the value must already exist in the points deck for the partner
-        for (card_value, count) in cards {
-            if !self.points_deck.contains_key(&card_value) {
-                match count {
-                    0 => return false,
-                    1 => twos -= 2,
-                    2 => twos -= 1,
-                    _ => {}
-                }
-                // requires too many wild cards to be valid points
-                if twos < 0 {
-                    return false;
+    /// Returns whether the cards are valid points, grouping them
+    /// as done by `MeldGroup::group_cards`
+    pub fn are_valid_points(&self, hand: &[Card], policy: &WildCardPolicy) -> bool {
+        self.are_valid_groups(&MeldGroup::group_cards(hand, &self.points_deck), policy)
+    }
+
+    /// Returns whether the groups are valid points for the team
+    ///
+    /// ## Rules
+    /// If the player opened, then every group needs to be point cards
+    /// meaning that it must either be a complete set of 3, Jokers, Two's,
+    /// or its value must already exist in the points deck for the partner.
+    /// Every group also has to follow the wild card policy along with the
+    /// cards already laid down for its value.
+    pub fn are_valid_groups(&self, groups: &[MeldGroup], policy: &WildCardPolicy) -> bool {
+        let mut values = HashSet::new();
+        groups.iter().all(|group| {
+            let melded = self
+                .points_deck
+                .get(&group.value)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let valid = match group.value {
+                CardValue::Two | CardValue::Joker => group.is_consistent(),
+                value => {
+                    // A value is laid down in a single group per move
+                    values.insert(value)
+                        && if melded.is_empty() {
+                            group.is_set()
+                        } else {
+                            group.is_consistent()
+                        }
                 }
-            }
-        }
-        true
+            };
+            valid && group.respects(policy, melded)
+        })
     }
 
     pub fn get_partner(&self, player_index: usize) -> Option<usize> {
@@ -146,7 +150,11 @@ impl Partners {
 
     pub fn get_points_total(&self) -> u16 {
         let mut total = 0;
-        for (_, v) in self.points_deck.iter() {
+        for v in self
+            .points_deck
+            .values()
+            .chain(Some(&self.queens_of_spades))
+        {
             for card in v {
                 total += match card.value {
                     CardValue::Ace => 15,
@@ -172,6 +180,130 @@ impl Partners {
     }
 }
 
+#[test]
+fn wilds_join_the_chosen_group() {
+    let card = |value| Card {
+        value,
+        suit: CardSuit::Hearts,
+    };
+    let mut partners = Partners::new(0, 2);
+    partners.add_points(vec![MeldGroup::new(
+        CardValue::Ace,
+        vec![card(CardValue::Ace); 3],
+    )]);
+
+    // A melded value can take a wild on its own
+    let groups = vec![MeldGroup::new(CardValue::Ace, vec![card(CardValue::Two)])];
+    assert!(partners.are_valid_groups(&groups, &WildCardPolicy::default()));
+
+    // A new value has to be a complete set
+    let incomplete = vec![MeldGroup::new(
+        CardValue::King,
+        vec![card(CardValue::King), card(CardValue::Two)],
+    )];
+    assert!(!partners.are_valid_groups(&incomplete, &WildCardPolicy::default()));
+
+    // A new value needs a card of its own
+    let lenient = WildCardPolicy {
+        max_wilds_per_meld: None,
+        wilds_may_outnumber_naturals: true,
+        allow_pure_wild_melds: true,
+    };
+    let only_wilds = vec![MeldGroup::new(
+        CardValue::King,
+        vec![card(CardValue::Two); 3],
+    )];
+    assert!(!partners.are_valid_groups(&only_wilds, &lenient));
+
+    partners.add_points(groups);
+    assert_eq!(partners.points_deck[&CardValue::Ace].len(), 4);
+    assert!(!partners.points_deck.contains_key(&CardValue::Two));
+}
+
+#[test]
+fn wild_card_policy_on_points() {
+    let card = |value| Card {
+        value,
+        suit: CardSuit::Spades,
+    };
+    let mut partners = Partners::new(1, 3);
+    partners.add_points(vec![MeldGroup::new(
+        CardValue::Nine,
+        vec![
+            card(CardValue::Nine),
+            card(CardValue::Nine),
+            card(CardValue::Two),
+        ],
+    )]);
+    let lenient = WildCardPolicy {
+        max_wilds_per_meld: None,
+        wilds_may_outnumber_naturals: true,
+        allow_pure_wild_melds: true,
+    };
+
+    // (policy, groups, expected)
+    let cases = vec![
+        // A wild joins a set that already holds one
+        (
+            WildCardPolicy::default(),
+            vec![MeldGroup::new(CardValue::Nine, vec![card(CardValue::Two)])],
+            true,
+        ),
+        // Three wilds on a set of two naturals
+        (
+            WildCardPolicy::default(),
+            vec![MeldGroup::new(
+                CardValue::Nine,
+                vec![card(CardValue::Two), card(CardValue::Two)],
+            )],
+            false,
+        ),
+        (
+            lenient,
+            vec![MeldGroup::new(
+                CardValue::Nine,
+                vec![card(CardValue::Two), card(CardValue::Two)],
+            )],
+            true,
+        ),
+        // Loose wilds
+        (
+            WildCardPolicy::default(),
+            vec![MeldGroup::new(CardValue::Two, vec![card(CardValue::Two)])],
+            false,
+        ),
+        (
+            lenient,
+            vec![MeldGroup::new(CardValue::Two, vec![card(CardValue::Two)])],
+            true,
+        ),
+        // Naturals are always welcome on a melded value
+        (
+            WildCardPolicy::default(),
+            vec![MeldGroup::new(CardValue::Nine, vec![card(CardValue::Nine)])],
+            true,
+        ),
+        // Jokers stand on their own
+        (
+            WildCardPolicy::default(),
+            vec![MeldGroup::new(
+                CardValue::Joker,
+                vec![card(CardValue::Joker)],
+            )],
+            true,
+        ),
+    ];
+    for (policy, groups, expected) in cases {
+        assert_eq!(
+            partners.are_valid_groups(&groups, &policy),
+            expected,
+            "{:?} under {:?}",
+            groups,
+            policy
+        );
+    }
+}
+
 #[test]
 fn test_status() {
     let mut partners = Partners::new(0, 1);
@@ -0,0 +1,2 @@
+mod suggestions;
+pub use suggestions::{suggest_melds, Suggestion, SuggestionKind};
@@ -0,0 +1,334 @@
+use crate::gameplay::{is_queen_of_spades, DDPState, MeldGroup, PlayerMove, QueenOfSpadesMeld};
+use crate::partners::WhoOpened;
+use card_game_engine::models::deck::{Card, CardValue};
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// What a suggestion lays down
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SuggestionKind {
+    /// A set made of cards of the hand of a single value
+    CompleteSet,
+    /// Cards of a single value completed into a set with the Two's of the hand
+    WildSet,
+    /// Cards of a value the team already laid down
+    ExtendMeld,
+    /// Cards to pick up the discard pile with, its top card letting the player open
+    PickupDiscard,
+}
+
+/// Cards of the hand the player could lay down
+#[derive(Clone, Debug, PartialEq)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    /// The value the cards are laid down as
+    pub value: CardValue,
+    /// The cards of the hand, by index
+    pub card_indices: Vec<usize>,
+    /// Points of the cards laid down, including the top card of the discard pile
+    pub points: u16,
+}
+
+impl Suggestion {
+    fn new(
+        kind: SuggestionKind,
+        value: CardValue,
+        card_indices: Vec<usize>,
+        hand: &[Card],
+    ) -> Self {
+        let points = card_indices
+            .iter()
+            .map(|i| PlayerMove::card_points(&hand[*i]))
+            .sum();
+        Self {
+            kind,
+            value,
+            card_indices,
+            points,
+        }
+    }
+}
+
+/// Looks for the cards a player could lay down
+///
+/// ## Purpose
+/// Helps players spot their sets. The suggestions are alternatives, a wild Two
+/// may be suggested for more than one of them.
+///
+/// ## Rules
+/// * Values the team laid down extend its points once the player opened
+/// * Other values are suggested as complete sets, or sets completed with the
+///     Two's of the hand as far as the wild card policy allows
+/// * Picking up the discard pile is suggested when the rules would let the
+///     player take it with the cards of the hand
+///
+/// ## Arguments
+/// `game` - The state of the game
+/// `seat` - The player the suggestions are for
+///
+/// ## Returns
+/// The suggestions, the ones worth the most points first
+pub fn suggest_melds(game: &DDPState, seat: usize) -> Vec<Suggestion> {
+    let (hand, team) = match (
+        game.default_state.players.get(seat),
+        game.get_team_index(seat),
+    ) {
+        (Some(player), Some(team)) => (&player.hand, &game.partners[team]),
+        _ => return vec![],
+    };
+    let opened = match team.who_opened(seat) {
+        WhoOpened::Me | WhoOpened::Both => true,
+        WhoOpened::Partner | WhoOpened::Nobody => false,
+    };
+    let twos = naturals_of(game, hand, CardValue::Two);
+
+    let mut suggestions = vec![];
+    for value in set_values(hand) {
+        let naturals = naturals_of(game, hand, value);
+        if naturals.is_empty() {
+            continue;
+        }
+        if opened && team.points_deck.contains_key(&value) {
+            suggestions.push(Suggestion::new(
+                SuggestionKind::ExtendMeld,
+                value,
+                naturals,
+                hand,
+            ));
+            continue;
+        }
+        if group_of(hand, value, &naturals).is_set() {
+            suggestions.push(Suggestion::new(
+                SuggestionKind::CompleteSet,
+                value,
+                naturals,
+                hand,
+            ));
+            continue;
+        }
+        let mut card_indices = naturals;
+        for two in twos.iter() {
+            if group_of(hand, value, &card_indices).is_set() {
+                break;
+            }
+            card_indices.push(*two);
+        }
+        let group = group_of(hand, value, &card_indices);
+        if group.is_set() && group.respects(&game.wild_cards, &[]) {
+            suggestions.push(Suggestion::new(
+                SuggestionKind::WildSet,
+                value,
+                card_indices,
+                hand,
+            ));
+        }
+    }
+    suggestions.extend(pickup_suggestion(game, seat, hand, &twos));
+
+    suggestions.sort_by_key(|suggestion| Reverse(suggestion.points));
+    suggestions
+}
+
+/// Returns the cards to pick up the discard pile with, when the rules allow it
+///
+/// ## Purpose
+/// The cards of the top card's value, completed with Two's unless the pile is
+/// frozen, are laid down along with every complete set of the hand.
+///
+/// ## Rules
+/// The cards are checked as `PlayerMove::TakeDiscardPile` checks them
+/// * The top card has to be one the player may pick up
+/// * A frozen pile needs a natural pair of the top card's value
+/// * The cards can't already open without the top card
+/// * With the top card they open under the opening rule, or make a set
+///     once the player opened
+fn pickup_suggestion(
+    game: &DDPState,
+    seat: usize,
+    hand: &[Card],
+    twos: &[usize],
+) -> Option<Suggestion> {
+    let top_card = game.default_state.deck.peek_top_discarded_card()?.clone();
+    if !PlayerMove::player_can_pickup_top_discard(seat, game) {
+        return None;
+    }
+    let mut card_indices = naturals_of(game, hand, top_card.value);
+    if !game.discard_pile_frozen {
+        // With the top card, the pair makes a set
+        let missing = 2usize.saturating_sub(card_indices.len());
+        card_indices.extend(twos.iter().take(missing));
+    }
+    for value in set_values(hand) {
+        let naturals = naturals_of(game, hand, value);
+        if value != top_card.value && group_of(hand, value, &naturals).is_set() {
+            card_indices.extend(naturals);
+        }
+    }
+
+    let mut cards: Vec<Card> = card_indices.iter().map(|i| hand[*i].clone()).collect();
+    if game.discard_pile_frozen && !PlayerMove::holds_natural_pair(&cards, &top_card) {
+        return None;
+    }
+    let groups = MeldGroup::group_cards(&cards, &HashMap::new());
+    if PlayerMove::opening_is_valid(game, seat, &groups) {
+        // The player opens without the discard pile
+        return None;
+    }
+    cards.push(top_card.clone());
+    let opens = if game.player_has_opened(seat) {
+        PlayerMove::hand_can_open(WhoOpened::Partner, &cards, &game.wild_cards)
+    } else {
+        let groups = MeldGroup::group_cards(&cards, &HashMap::new());
+        PlayerMove::opening_is_valid(game, seat, &groups)
+    };
+    if !opens {
+        return None;
+    }
+
+    let mut suggestion = Suggestion::new(
+        SuggestionKind::PickupDiscard,
+        top_card.value,
+        card_indices,
+        hand,
+    );
+    suggestion.points += PlayerMove::card_points(&top_card);
+    Some(suggestion)
+}
+
+/// Returns the values of the hand sets can be made of, in the order of the hand
+fn set_values(hand: &[Card]) -> Vec<CardValue> {
+    let mut values = vec![];
+    for card in hand {
+        if card.value != CardValue::Two
+            && card.value != CardValue::Joker
+            && !values.contains(&card.value)
+        {
+            values.push(card.value);
+        }
+    }
+    values
+}
+
+/// Returns the indices of the cards of the value, leaving out the Queens of
+/// Spades when they are only melded on their own
+fn naturals_of(game: &DDPState, hand: &[Card], value: CardValue) -> Vec<usize> {
+    let standalone = game.queen_of_spades.meld == QueenOfSpadesMeld::Standalone;
+    hand.iter()
+        .enumerate()
+        .filter(|(_, card)| card.value == value && !(standalone && is_queen_of_spades(card)))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn group_of(hand: &[Card], value: CardValue, card_indices: &[usize]) -> MeldGroup {
+    MeldGroup::new(
+        value,
+        card_indices.iter().map(|i| hand[*i].clone()).collect(),
+    )
+}
+
+#[test]
+fn suggest_sets() {
+    use crate::gameplay::DameDePiqueGameBuilder;
+    use card_game_engine::builder::GameBuilder;
+    use card_game_engine::models::deck::CardSuit;
+
+    let card = |value| Card {
+        value,
+        suit: CardSuit::Hearts,
+    };
+    let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+    game.default_state.players[0].hand = vec![
+        card(CardValue::Ace),
+        card(CardValue::Seven),
+        card(CardValue::Ace),
+        card(CardValue::Seven),
+        card(CardValue::Ace),
+        card(CardValue::Two),
+        card(CardValue::Four),
+        card(CardValue::Joker),
+    ];
+
+    let suggestions = suggest_melds(&game, 0);
+    assert_eq!(
+        suggestions
+            .iter()
+            .map(|suggestion| (suggestion.kind, suggestion.value))
+            .collect::<Vec<_>>(),
+        vec![
+            (SuggestionKind::CompleteSet, CardValue::Ace),
+            (SuggestionKind::WildSet, CardValue::Seven),
+        ]
+    );
+    assert_eq!(suggestions[1].card_indices, vec![1, 3, 5]);
+    assert_eq!(suggestions[1].points, 30);
+
+    // Once opened, the cards of a value the team laid down extend it
+    game.partners[0].add_points(vec![MeldGroup::new(
+        CardValue::Four,
+        vec![card(CardValue::Four); 3],
+    )]);
+    game.partners[0].update_status(0);
+    let suggestions = suggest_melds(&game, 0);
+    assert!(suggestions.contains(&Suggestion {
+        kind: SuggestionKind::ExtendMeld,
+        value: CardValue::Four,
+        card_indices: vec![6],
+        points: 5,
+    }));
+}
+
+#[test]
+fn suggest_discard_pickup() {
+    use crate::gameplay::DameDePiqueGameBuilder;
+    use card_game_engine::builder::GameBuilder;
+    use card_game_engine::models::deck::CardSuit;
+
+    let card = |value| Card {
+        value,
+        suit: CardSuit::Clubs,
+    };
+    let mut game = DameDePiqueGameBuilder::initialize_game().unwrap();
+    game.discard_pile_frozen = false;
+    game.default_state.deck.discard_card(card(CardValue::Seven));
+    game.default_state.players[0].hand = vec![
+        card(CardValue::Seven),
+        card(CardValue::King),
+        card(CardValue::King),
+        card(CardValue::King),
+        card(CardValue::Ace),
+        card(CardValue::Ace),
+        card(CardValue::Ace),
+        card(CardValue::Four),
+    ];
+
+    // A single Seven doesn't let the player open with the top card
+    assert!(suggest_melds(&game, 0)
+        .iter()
+        .all(|suggestion| suggestion.kind != SuggestionKind::PickupDiscard));
+
+    game.default_state.players[0]
+        .hand
+        .push(card(CardValue::Seven));
+    let suggestions = suggest_melds(&game, 0);
+    assert_eq!(
+        suggestions[0],
+        Suggestion {
+            kind: SuggestionKind::PickupDiscard,
+            value: CardValue::Seven,
+            card_indices: vec![0, 8, 1, 2, 3, 4, 5, 6],
+            points: 90,
+        }
+    );
+    // The game itself is left untouched
+    assert_eq!(game.default_state.players[0].hand.len(), 9);
+    assert!(game.default_state.deck.peek_top_discarded_card().is_some());
+
+    // A frozen pile needs a natural pair, a Two doesn't stand in for the Seven
+    game.discard_pile_frozen = true;
+    game.default_state.players[0].hand[8] = card(CardValue::Two);
+    assert!(suggest_melds(&game, 0)
+        .iter()
+        .all(|suggestion| suggestion.kind != SuggestionKind::PickupDiscard));
+}
@@ -35,6 +35,54 @@ fn calculate_total() -> Result<(), DameDePiqueError> {
     Ok(())
 }
 
+#[test]
+fn queen_of_spades_holding_penalty() -> Result<(), DameDePiqueError> {
+    let mut game_state = DameDePiqueGame::new_game()?;
+    game_state.queen_of_spades.holding_penalty = 50;
+    game_state.default_state.players[0].hand = vec![Card {
+        suit: CardSuit::Clubs,
+        value: CardValue::Ace,
+    }];
+    game_state.default_state.players[2].hand = vec![
+        Card {
+            suit: CardSuit::Spades,
+            value: CardValue::Queen,
+        },
+        Card {
+            suit: CardSuit::Hearts,
+            value: CardValue::Four,
+        },
+    ];
+    assert_eq!(
+        GameStatus::RoundOver,
+        DameDePiqueGame::game_action(PlayerMove::Discard(0), &mut game_state)?
+    );
+
+    let summary = game_state.last_round.clone().unwrap();
+    assert_eq!(summary.teams[0].hand_points, 105);
+    assert_eq!(summary.teams[0].caught_with_queen_of_spades, vec![2]);
+    assert_eq!(summary.teams[0].queen_of_spades_penalty, 50);
+    assert_eq!(summary.teams[0].total(), -155);
+    assert_eq!(game_state.partners[0].overall_points, -155);
+    Ok(())
+}
+
+#[test]
+fn initialize_game_with_player_names() -> Result<(), DameDePiqueError> {
+    let names = vec!["alice".to_string(), "bob".to_string()];
+    let game_state = DameDePiqueGameBuilder::initialize_game_with_players(&names)?;
+    assert_eq!(game_state.default_state.players[0].name, "alice");
+    assert_eq!(game_state.default_state.players[1].name, "bob");
+    assert_eq!(game_state.default_state.players[2].name, "Player 2");
+    assert_eq!(game_state.default_state.players[3].name, "Player 3");
+
+    assert_eq!(game_state.get_team_index(0), Some(0));
+    assert_eq!(game_state.get_team_index(1), Some(1));
+    assert_eq!(game_state.get_team_index(2), Some(0));
+    assert!(!game_state.player_has_opened(0));
+    Ok(())
+}
+
 // #[test]
 // fn player_b_open() -> Result<(), DameDePiqueError> {
 
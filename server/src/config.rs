@@ -1,67 +1,460 @@
 use serde::Deserialize;
+use std::convert::TryFrom;
 use std::env;
-use std::fs::File;
-use std::io::Read;
-use std::net::{IpAddr, Ipv4Addr};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::Arc;
 
 const DEFAULT_NETWORK_CONFIG_PATH: &str = "../common/network.json";
+const DEFAULT_WEBSOCKET_HOST: &str = "127.0.0.1";
+const DEFAULT_SERVER_HOST: &str = "0.0.0.0";
+const DEFAULT_SERVER_PORT: u16 = 8000;
+const DEFAULT_RECONNECT_GRACE_SECONDS: u64 = 60;
+const DEFAULT_IDLE_SESSION_TTL_SECONDS: u64 = 30 * 60;
+const DEFAULT_FINISHED_SESSION_TTL_SECONDS: u64 = 5 * 60;
+const DEFAULT_REAPER_INTERVAL_SECONDS: u64 = 60;
+const DEFAULT_SNAPSHOT_DIR: &str = "snapshots";
+const DEFAULT_RATE_LIMIT_WINDOW_SECONDS: u64 = 60;
+const DEFAULT_IP_REQUESTS_PER_WINDOW: u32 = 600;
+const DEFAULT_PLAYER_REQUESTS_PER_WINDOW: u32 = 60;
+const DEFAULT_MAX_SESSIONS_PER_PLAYER: usize = 3;
+/// Longest duration accepted for any setting given in seconds, a week
+const MAX_DURATION_SECONDS: u64 = 7 * 24 * 60 * 60;
+/// Most game sessions a player may be allowed to host at once
+const MAX_SESSIONS_PER_PLAYER_LIMIT: u64 = 100;
+
+/// The configuration shared by every handler, loaded once at startup
+pub type SharedConfig = Arc<Config>;
+
+/// Certificate and private key the server uses to serve HTTPS and `wss://`
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
 
 /// Server Configuration of ip and port
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Config {
+    /// Host the clients use to reach the websocket, an address or a hostname
     pub websocket_host: String,
-    pub host: IpAddr,
+    /// Whether the clients connect to the websocket with `wss://`
+    pub websocket_secure: bool,
+    /// Host the server listens on, an IPv4 or IPv6 address or a hostname
+    pub host: String,
     pub port: u16,
+    /// The address the server listens on, resolved from the host and port
+    pub address: SocketAddr,
+    /// Serves the server over TLS when set
+    pub tls: Option<TlsConfig>,
+    /// How long a disconnected player's seat is held in an active game
+    pub reconnect_grace_seconds: u64,
+    /// Whether a bot takes over a seat once its reservation expires
+    pub bot_takeover: bool,
+    /// How long a game session may go without activity before being removed
+    pub idle_session_ttl_seconds: u64,
+    /// How long a finished game session is kept before being removed
+    pub finished_session_ttl_seconds: u64,
+    /// How often abandoned game sessions are looked for
+    pub reaper_interval_seconds: u64,
+    /// Where the game sessions are saved when the server shuts down
+    pub snapshot_dir: String,
+    /// Length of the windows requests are counted in
+    pub rate_limit_window_seconds: u64,
+    /// Requests an IP address may make per window
+    pub ip_requests_per_window: u32,
+    /// Lobby and gameplay requests a player may make per window
+    pub player_requests_per_window: u32,
+    /// Game sessions a player may host at once
+    pub max_sessions_per_player: usize,
 }
 
-/// JSON representation of the loaded json data
+impl Config {
+    /// Returns the url a player connects to for their websocket
+    ///
+    /// ## Arguments
+    /// `username` - the player connecting
+    pub fn websocket_url(&self, username: &str) -> String {
+        let scheme = if self.websocket_secure || self.tls.is_some() {
+            "wss"
+        } else {
+            "ws"
+        };
+        // IPv6 addresses must be wrapped in brackets to be followed by a port
+        let host = match self.websocket_host.parse::<Ipv6Addr>() {
+            Ok(_) => format!("[{}]", self.websocket_host),
+            Err(_) => self.websocket_host.clone(),
+        };
+        format!("{}://{}:{}/ws/{}", scheme, host, self.port, username)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            websocket_host: DEFAULT_WEBSOCKET_HOST.into(),
+            websocket_secure: false,
+            host: DEFAULT_SERVER_HOST.into(),
+            port: DEFAULT_SERVER_PORT,
+            address: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), DEFAULT_SERVER_PORT),
+            tls: None,
+            reconnect_grace_seconds: DEFAULT_RECONNECT_GRACE_SECONDS,
+            bot_takeover: false,
+            idle_session_ttl_seconds: DEFAULT_IDLE_SESSION_TTL_SECONDS,
+            finished_session_ttl_seconds: DEFAULT_FINISHED_SESSION_TTL_SECONDS,
+            reaper_interval_seconds: DEFAULT_REAPER_INTERVAL_SECONDS,
+            snapshot_dir: DEFAULT_SNAPSHOT_DIR.into(),
+            rate_limit_window_seconds: DEFAULT_RATE_LIMIT_WINDOW_SECONDS,
+            ip_requests_per_window: DEFAULT_IP_REQUESTS_PER_WINDOW,
+            player_requests_per_window: DEFAULT_PLAYER_REQUESTS_PER_WINDOW,
+            max_sessions_per_player: DEFAULT_MAX_SESSIONS_PER_PLAYER,
+        }
+    }
+}
+
+/// Reasons the server configuration could not be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read
+    Read(String, io::Error),
+    /// The configuration file is not valid JSON
+    Parse(String, serde_json::Error),
+    /// An environment variable has a value of the wrong type
+    InvalidValue(String, String),
+    /// The server host is neither an address nor a known hostname
+    UnresolvedHost(String),
+    /// Only one of the TLS certificate and private key was given
+    IncompleteTls,
+    /// A setting is outside of the values it accepts
+    OutOfRange(String, u64, RangeInclusive<u64>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "Could not read {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "Could not parse {}: {}", path, e),
+            ConfigError::InvalidValue(variable, value) => {
+                write!(f, "Invalid value {:?} for {}", value, variable)
+            }
+            ConfigError::UnresolvedHost(host) => write!(f, "Could not resolve host {}", host),
+            ConfigError::IncompleteTls => {
+                write!(f, "TLS needs both tls_cert_path and tls_key_path to be set")
+            }
+            ConfigError::OutOfRange(setting, value, range) => write!(
+                f,
+                "{} must be from {} to {}, not {}",
+                setting,
+                range.start(),
+                range.end(),
+                value
+            ),
+        }
+    }
+}
+
+/// JSON representation of the loaded json data, every field is optional
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct ConfigJson {
     websocket_host: String,
+    websocket_secure: bool,
     server_host: String,
     server_port: u16,
+    tls_cert_path: Option<String>,
+    tls_key_path: Option<String>,
+    reconnect_grace_seconds: u64,
+    bot_takeover: bool,
+    idle_session_ttl_seconds: u64,
+    finished_session_ttl_seconds: u64,
+    reaper_interval_seconds: u64,
+    snapshot_dir: String,
+    rate_limit_window_seconds: u64,
+    ip_requests_per_window: u32,
+    player_requests_per_window: u32,
+    max_sessions_per_player: usize,
+}
+
+impl Default for ConfigJson {
+    fn default() -> Self {
+        let config = Config::default();
+        Self {
+            websocket_host: config.websocket_host,
+            websocket_secure: config.websocket_secure,
+            server_host: config.host,
+            server_port: config.port,
+            tls_cert_path: None,
+            tls_key_path: None,
+            reconnect_grace_seconds: config.reconnect_grace_seconds,
+            bot_takeover: config.bot_takeover,
+            idle_session_ttl_seconds: config.idle_session_ttl_seconds,
+            finished_session_ttl_seconds: config.finished_session_ttl_seconds,
+            reaper_interval_seconds: config.reaper_interval_seconds,
+            snapshot_dir: config.snapshot_dir,
+            rate_limit_window_seconds: config.rate_limit_window_seconds,
+            ip_requests_per_window: config.ip_requests_per_window,
+            player_requests_per_window: config.player_requests_per_window,
+            max_sessions_per_player: config.max_sessions_per_player,
+        }
+    }
 }
 
-impl Into<Config> for ConfigJson {
-    fn into(self) -> Config {
-        // Host must be format XXX.XXX.XXX.XXX - EX: 127.0.0.1
-        // Port must be u16 integer
-        let mut host_string = self.server_host.split('.');
-        if let (Some(a), Some(b), Some(c), Some(d)) = (
-            host_string.next(),
-            host_string.next(),
-            host_string.next(),
-            host_string.next(),
-        ) {
-            return Config {
-                websocket_host: self.websocket_host,
-                host: IpAddr::V4(Ipv4Addr::new(
-                    a.parse::<u8>().unwrap(),
-                    b.parse::<u8>().unwrap(),
-                    c.parse::<u8>().unwrap(),
-                    d.parse::<u8>().unwrap(),
-                )),
-                port: self.server_port,
-            };
+impl ConfigJson {
+    /// Overrides every field set in the environment, the variable
+    /// of a field is its name in upper case, EX: SERVER_PORT
+    fn apply_env_overrides(&mut self) -> Result<(), ConfigError> {
+        env_override("WEBSOCKET_HOST", &mut self.websocket_host)?;
+        env_override("WEBSOCKET_SECURE", &mut self.websocket_secure)?;
+        env_override("SERVER_HOST", &mut self.server_host)?;
+        env_override("SERVER_PORT", &mut self.server_port)?;
+        env_override_path("TLS_CERT_PATH", &mut self.tls_cert_path);
+        env_override_path("TLS_KEY_PATH", &mut self.tls_key_path);
+        env_override("RECONNECT_GRACE_SECONDS", &mut self.reconnect_grace_seconds)?;
+        env_override("BOT_TAKEOVER", &mut self.bot_takeover)?;
+        env_override(
+            "IDLE_SESSION_TTL_SECONDS",
+            &mut self.idle_session_ttl_seconds,
+        )?;
+        env_override(
+            "FINISHED_SESSION_TTL_SECONDS",
+            &mut self.finished_session_ttl_seconds,
+        )?;
+        env_override("REAPER_INTERVAL_SECONDS", &mut self.reaper_interval_seconds)?;
+        env_override("SNAPSHOT_DIR", &mut self.snapshot_dir)?;
+        env_override(
+            "RATE_LIMIT_WINDOW_SECONDS",
+            &mut self.rate_limit_window_seconds,
+        )?;
+        env_override("IP_REQUESTS_PER_WINDOW", &mut self.ip_requests_per_window)?;
+        env_override(
+            "PLAYER_REQUESTS_PER_WINDOW",
+            &mut self.player_requests_per_window,
+        )?;
+        env_override("MAX_SESSIONS_PER_PLAYER", &mut self.max_sessions_per_player)?;
+        Ok(())
+    }
+
+    /// Checks that the durations and limits are within the values the server runs with
+    ///
+    /// ## Rules
+    /// * Durations last at least a second and at most `MAX_DURATION_SECONDS`
+    /// * Rate limits allow at least one request per window
+    /// * Players may host at least one game session
+    fn validate(&self) -> Result<(), ConfigError> {
+        let durations = [
+            ("reconnect_grace_seconds", self.reconnect_grace_seconds),
+            ("idle_session_ttl_seconds", self.idle_session_ttl_seconds),
+            (
+                "finished_session_ttl_seconds",
+                self.finished_session_ttl_seconds,
+            ),
+            ("reaper_interval_seconds", self.reaper_interval_seconds),
+            ("rate_limit_window_seconds", self.rate_limit_window_seconds),
+        ];
+        for (setting, seconds) in durations.iter() {
+            check_range(setting, *seconds, 1..=MAX_DURATION_SECONDS)?;
         }
-        panic!();
+        let limits = [
+            ("ip_requests_per_window", self.ip_requests_per_window),
+            (
+                "player_requests_per_window",
+                self.player_requests_per_window,
+            ),
+        ];
+        for (setting, limit) in limits.iter() {
+            check_range(setting, u64::from(*limit), 1..=u64::from(u32::MAX))?;
+        }
+        check_range(
+            "max_sessions_per_player",
+            self.max_sessions_per_player as u64,
+            1..=MAX_SESSIONS_PER_PLAYER_LIMIT,
+        )
+    }
+}
+
+/// Returns an error naming the setting when its value is outside of the range
+fn check_range(setting: &str, value: u64, range: RangeInclusive<u64>) -> Result<(), ConfigError> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(ConfigError::OutOfRange(setting.to_string(), value, range))
+    }
+}
+
+impl TryFrom<ConfigJson> for Config {
+    type Error = ConfigError;
+
+    fn try_from(other: ConfigJson) -> Result<Config, ConfigError> {
+        other.validate()?;
+
+        // Host may be an IPv4 or IPv6 address or a hostname - EX: 127.0.0.1, ::1, localhost
+        let address = (other.server_host.as_str(), other.server_port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| ConfigError::UnresolvedHost(other.server_host.clone()))?;
+
+        let tls = match (other.tls_cert_path, other.tls_key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                // Fail at startup rather than when the first client connects
+                for path in [&cert_path, &key_path].iter() {
+                    fs::metadata(path).map_err(|e| ConfigError::Read(path.to_string(), e))?;
+                }
+                Some(TlsConfig {
+                    cert_path,
+                    key_path,
+                })
+            }
+            (None, None) => None,
+            _ => return Err(ConfigError::IncompleteTls),
+        };
+
+        Ok(Config {
+            websocket_host: other.websocket_host,
+            websocket_secure: other.websocket_secure,
+            host: other.server_host,
+            port: other.server_port,
+            address,
+            tls,
+            reconnect_grace_seconds: other.reconnect_grace_seconds,
+            bot_takeover: other.bot_takeover,
+            idle_session_ttl_seconds: other.idle_session_ttl_seconds,
+            finished_session_ttl_seconds: other.finished_session_ttl_seconds,
+            reaper_interval_seconds: other.reaper_interval_seconds,
+            snapshot_dir: other.snapshot_dir,
+            rate_limit_window_seconds: other.rate_limit_window_seconds,
+            ip_requests_per_window: other.ip_requests_per_window,
+            player_requests_per_window: other.player_requests_per_window,
+            max_sessions_per_player: other.max_sessions_per_player,
+        })
+    }
+}
+
+/// Replaces the value with the environment variable, when it is set
+fn env_override<T: FromStr>(variable: &str, value: &mut T) -> Result<(), ConfigError> {
+    if let Ok(raw) = env::var(variable) {
+        *value = raw
+            .parse()
+            .map_err(|_| ConfigError::InvalidValue(variable.to_string(), raw.clone()))?;
+    }
+    Ok(())
+}
+
+/// Sets the path to the environment variable, when it is set
+fn env_override_path(variable: &str, path: &mut Option<String>) {
+    if let Ok(raw) = env::var(variable) {
+        *path = Some(raw);
     }
 }
 
 /// Loads the network json file and maps it to the config json struct
 /// Then converts the json struct to a Config object
 ///
-/// # Returns
-/// A config object with the host and port of the server
-pub fn load_config() -> Config {
-    let path = match env::var("NETWORK_CONFIG_PATH") {
-        Ok(path) => path,
-        Err(_) => DEFAULT_NETWORK_CONFIG_PATH.to_string(),
+/// ## Purpose
+/// Called once at startup. Missing fields take their default value and
+/// environment variables take precedence over the file. The default file
+/// may be missing, in which case only the defaults and environment are used.
+///
+/// ## Returns
+/// A config object with the host and port of the server, or why it could not be loaded
+pub fn load_config() -> Result<Config, ConfigError> {
+    let (path, explicit_path) = match env::var("NETWORK_CONFIG_PATH") {
+        Ok(path) => (path, true),
+        Err(_) => (DEFAULT_NETWORK_CONFIG_PATH.to_string(), false),
     };
 
-    let mut file = File::open(path).unwrap();
-    let mut data = String::new();
-    file.read_to_string(&mut data).unwrap();
-    let config: ConfigJson = serde_json::from_str(&data).unwrap();
-    config.into()
+    let mut config = match fs::read_to_string(&path) {
+        Ok(data) => serde_json::from_str::<ConfigJson>(&data)
+            .map_err(|e| ConfigError::Parse(path.clone(), e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit_path => {
+            println!("No configuration found at {}, using the defaults", path);
+            ConfigJson::default()
+        }
+        Err(e) => return Err(ConfigError::Read(path, e)),
+    };
+    config.apply_env_overrides()?;
+    Config::try_from(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_overrides_the_file() {
+        // Every variable is set in this test alone, tests run in parallel
+        let variables = [
+            ("WEBSOCKET_HOST", "play.example.com"),
+            ("WEBSOCKET_SECURE", "true"),
+            ("SERVER_PORT", "9000"),
+            ("TLS_CERT_PATH", "cert.pem"),
+            ("RECONNECT_GRACE_SECONDS", "90"),
+            ("IP_REQUESTS_PER_WINDOW", "1000"),
+            ("MAX_SESSIONS_PER_PLAYER", "5"),
+        ];
+        for (variable, value) in variables.iter() {
+            env::set_var(variable, value);
+        }
+        let mut config = ConfigJson::default();
+        let overridden = config.apply_env_overrides();
+        env::set_var("SERVER_PORT", "eighty");
+        let invalid = ConfigJson::default().apply_env_overrides();
+        for (variable, _) in variables.iter() {
+            env::remove_var(variable);
+        }
+
+        assert!(overridden.is_ok());
+        assert_eq!(config.websocket_host, "play.example.com");
+        assert!(config.websocket_secure);
+        assert_eq!(config.server_port, 9000);
+        assert_eq!(config.tls_cert_path.as_deref(), Some("cert.pem"));
+        assert_eq!(config.reconnect_grace_seconds, 90);
+        assert_eq!(config.ip_requests_per_window, 1000);
+        assert_eq!(config.max_sessions_per_player, 5);
+        match invalid {
+            Err(ConfigError::InvalidValue(variable, value)) => {
+                assert_eq!(
+                    (variable.as_str(), value.as_str()),
+                    ("SERVER_PORT", "eighty")
+                )
+            }
+            other => panic!("SERVER_PORT=eighty was not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn settings_out_of_range_are_rejected() {
+        let config = ConfigJson {
+            player_requests_per_window: 0,
+            ..ConfigJson::default()
+        };
+        match Config::try_from(config) {
+            Err(ConfigError::OutOfRange(setting, 0, _)) => {
+                assert_eq!(setting, "player_requests_per_window")
+            }
+            other => panic!("no requests per window was accepted: {:?}", other),
+        }
+        assert!(Config::try_from(ConfigJson::default()).is_ok());
+    }
+
+    #[test]
+    fn websocket_url_brackets_ipv6_hosts() {
+        let config = Config {
+            websocket_host: "::1".into(),
+            websocket_secure: true,
+            port: 8443,
+            ..Config::default()
+        };
+        assert_eq!(config.websocket_url("north"), "wss://[::1]:8443/ws/north");
+
+        let config = Config::default();
+        assert_eq!(
+            config.websocket_url("north"),
+            "ws://127.0.0.1:8000/ws/north"
+        );
+    }
 }
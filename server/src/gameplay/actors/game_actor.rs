@@ -0,0 +1,1223 @@
+use crate::config::SharedConfig;
+use crate::gameplay::bot_player;
+use crate::gameplay::gameplay_handlers::{
+    game_action, handle_game_status, send_message_to_players, send_state_to_spectators,
+    send_text_to_players,
+};
+use crate::gameplay::gameplay_models::{
+    HintResponse, PlayerGameStateResponse, SpectatorGameStateResponse,
+};
+use crate::gameplay::turn_timer::TurnTimer;
+use crate::lobby::{Lobby, LobbyEvent};
+use crate::models::{
+    AlreadySeated, GameClosedResponse, GameResponse, GameSession, HintsDisabled, InvalidCards,
+    LobbyEntry, Players, SeatStatusResponse, StartGameResponse, TooManyPlayers, TooManyRequests,
+    UndoDisabled, WebSocketResponse, SEATS,
+};
+use crate::rate_limit::SharedRateLimiter;
+use crate::reaper::CloseReason;
+use crate::Result;
+use game::gameplay::{DameDePiqueGameBuilder, MeldGroup, PlayerMove};
+use game::models::{Card, CardValue};
+use game::rules::GameStatus;
+use game::suggestions::suggest_melds;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::interval;
+use warp::ws::Message;
+
+/// A move played by the player whose turn it is, cards are
+/// given by their identifier, see `CardId`
+#[derive(Debug, Clone)]
+pub enum GameAction {
+    Draw,
+    Discard(String),
+    Open(Meld),
+    AddPoints(Meld),
+    PickupDiscard(Vec<String>),
+    /// Takes back the last meld of the turn, when the table allows it
+    Undo,
+}
+
+/// Cards of the current player's hand laid down as points
+#[derive(Debug, Clone)]
+pub enum Meld {
+    /// Cards grouped by the rules, see `MeldGroup::group_cards`
+    Cards(Vec<String>),
+    /// Cards grouped by the player
+    Groups(Vec<CardGroup>),
+}
+
+/// Cards of the hand laid down as the points of a single value
+#[derive(Debug, Clone)]
+pub struct CardGroup {
+    /// The value the group is filed under, taken from its cards when None
+    pub value: Option<CardValue>,
+    pub card_ids: Vec<String>,
+}
+
+/// Commands sent to a game actor, they are handled one at a time
+/// in the order they were sent
+pub enum GameCommand {
+    /// Plays a move for the player whose turn it is
+    Play {
+        action: GameAction,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    /// Seats a player at the table
+    Join {
+        username: String,
+        reply: oneshot::Sender<Result<GameResponse>>,
+    },
+    /// Adds a spectator to the table
+    Spectate {
+        username: String,
+        reply: oneshot::Sender<Result<GameResponse>>,
+    },
+    /// Starts the game, or its next round
+    Start { reply: oneshot::Sender<()> },
+    /// Takes a player who unregistered away from the table,
+    /// replies whether they held a seat
+    Leave {
+        username: String,
+        reply: oneshot::Sender<bool>,
+    },
+    /// A player's websocket closed, replies when their seat reservation
+    /// started if their seat is being held for them
+    Disconnect {
+        username: String,
+        reply: oneshot::Sender<Option<Instant>>,
+    },
+    /// A player's websocket connected
+    Reconnect { username: String },
+    /// The grace period of a disconnected player is over
+    ReservationExpired {
+        username: String,
+        disconnected_at: Instant,
+    },
+    /// The state of the game as seen from a seat
+    PlayerState {
+        seat: usize,
+        reply: oneshot::Sender<Result<PlayerGameStateResponse>>,
+    },
+    /// The public state of the game
+    SpectatorState {
+        reply: oneshot::Sender<SpectatorGameStateResponse>,
+    },
+    /// The lobby information of the game
+    Info {
+        reply: oneshot::Sender<GameResponse>,
+    },
+    /// Changes the rules of the table, the rules left as None are kept
+    SetRules {
+        allow_undo: Option<bool>,
+        allow_hints: Option<bool>,
+        reply: oneshot::Sender<GameResponse>,
+    },
+    /// Meld suggestions for the player in the given seat
+    Hints {
+        seat: usize,
+        reply: oneshot::Sender<Result<Vec<HintResponse>>>,
+    },
+    /// How the game is listed in the lobby
+    Listing { reply: oneshot::Sender<LobbyEntry> },
+    /// A copy of the whole game session
+    Snapshot { reply: oneshot::Sender<GameSession> },
+    /// Releases everyone at the table and stops the actor
+    Close { reason: CloseReason },
+}
+
+/// Handle to the task owning a game session
+///
+/// ## Purpose
+/// Every game session runs in its own task which is the only one to ever
+/// touch its state. Handlers send it commands instead of locking the session,
+/// so moves are played in the order they were received and a slow table never
+/// blocks the others.
+#[derive(Clone)]
+pub struct GameHandle {
+    /// The player who created the game session
+    pub host: String,
+    pub invite_code: String,
+    /// Whether the table is hidden from the lobby
+    pub private: bool,
+    sender: mpsc::UnboundedSender<GameCommand>,
+}
+
+impl GameHandle {
+    /// Spawns the task owning the game session
+    ///
+    /// ## Arguments
+    /// `session` - the game session handed to the task
+    /// `players` - persistent collection of players
+    /// `config` - the server configuration
+    /// `limiter` - the rate limits the players' requests count against
+    /// `lobby` - the lobby updates channel
+    pub fn spawn(
+        session: GameSession,
+        players: Players,
+        config: SharedConfig,
+        limiter: SharedRateLimiter,
+        lobby: Lobby,
+    ) -> GameHandle {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let host = session.inner.players.first().cloned().unwrap_or_default();
+        let invite_code = session.inner.invite_code.clone();
+        let private = session.inner.private;
+        let actor = GameActor {
+            session,
+            players,
+            config,
+            limiter,
+            timer: TurnTimer::default(),
+            lobby,
+            listed: None,
+        };
+        tokio::task::spawn(actor.run(receiver));
+        GameHandle {
+            host,
+            invite_code,
+            private,
+            sender,
+        }
+    }
+
+    /// Sends a command without waiting for it to be handled
+    ///
+    /// ## Returns
+    /// Whether the game session is still running
+    pub fn notify(&self, command: GameCommand) -> bool {
+        self.sender.send(command).is_ok()
+    }
+
+    /// Sends a command and waits for its reply
+    ///
+    /// ## Returns
+    /// None if the game session stopped before replying
+    pub async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> GameCommand,
+    ) -> Option<T> {
+        let (reply, response) = oneshot::channel();
+        self.notify(command(reply));
+        response.await.ok()
+    }
+}
+
+struct GameActor {
+    session: GameSession,
+    players: Players,
+    config: SharedConfig,
+    limiter: SharedRateLimiter,
+    timer: TurnTimer,
+    lobby: Lobby,
+    /// The lobby entry last published for the game, None while it isn't listed
+    listed: Option<LobbyEntry>,
+}
+
+impl GameActor {
+    /// Handles commands until the game session is closed
+    /// or every handle to it was dropped
+    async fn run(mut self, mut receiver: mpsc::UnboundedReceiver<GameCommand>) {
+        let mut ticks = interval(Duration::from_secs(1));
+        self.update_lobby();
+        loop {
+            tokio::select! {
+                command = receiver.recv() => match command {
+                    Some(GameCommand::Close { reason }) => {
+                        self.close(reason).await;
+                        return;
+                    }
+                    Some(command) => self.handle(command).await,
+                    None => return,
+                },
+                _ = ticks.tick() => {
+                    self.timer.tick(&mut self.session, self.players.clone()).await;
+                }
+            }
+            self.update_lobby();
+        }
+    }
+
+    /// Publishes the lobby entry of public games when it changed
+    fn update_lobby(&mut self) {
+        if self.session.inner.private {
+            return;
+        }
+        let entry = self.session.lobby_entry();
+        if self.listed.as_ref() != Some(&entry) {
+            self.lobby.publish(LobbyEvent::Updated {
+                table: entry.clone(),
+            });
+            self.listed = Some(entry);
+        }
+    }
+
+    async fn handle(&mut self, command: GameCommand) {
+        // Replies are dropped when the requester stopped waiting for them
+        match command {
+            GameCommand::Play { action, reply } => {
+                let _ = reply.send(self.play(action).await);
+            }
+            GameCommand::Join { username, reply } => {
+                let _ = reply.send(self.join(username).await);
+            }
+            GameCommand::Spectate { username, reply } => {
+                let _ = reply.send(self.spectate(username).await);
+            }
+            GameCommand::Start { reply } => {
+                self.start().await;
+                let _ = reply.send(());
+            }
+            GameCommand::Leave { username, reply } => {
+                let _ = reply.send(self.leave(&username).await);
+            }
+            GameCommand::Disconnect { username, reply } => {
+                let _ = reply.send(self.disconnect(username).await);
+            }
+            GameCommand::Reconnect { username } => self.reconnect(username).await,
+            GameCommand::ReservationExpired {
+                username,
+                disconnected_at,
+            } => self.expire_reservation(username, disconnected_at).await,
+            GameCommand::PlayerState { seat, reply } => {
+                let _ = reply.send(self.player_state(seat));
+            }
+            GameCommand::SpectatorState { reply } => {
+                let _ = reply.send(SpectatorGameStateResponse::from(&self.session));
+            }
+            GameCommand::Info { reply } => {
+                let _ = reply.send(self.session.inner.clone());
+            }
+            GameCommand::SetRules {
+                allow_undo,
+                allow_hints,
+                reply,
+            } => {
+                let rules = &mut self.session.inner;
+                rules.undo_allowed = allow_undo.unwrap_or(rules.undo_allowed);
+                rules.hints_allowed = allow_hints.unwrap_or(rules.hints_allowed);
+                self.session.touch();
+                let _ = reply.send(self.session.inner.clone());
+            }
+            GameCommand::Hints { seat, reply } => {
+                let _ = reply.send(self.hints(seat));
+            }
+            GameCommand::Listing { reply } => {
+                let _ = reply.send(self.session.lobby_entry());
+            }
+            GameCommand::Snapshot { reply } => {
+                let _ = reply.send(self.session.clone());
+            }
+            GameCommand::Close { reason } => self.close(reason).await,
+        }
+    }
+
+    /// Counts a request made for the player in the given seat against their rate limit
+    fn allow_seat(&self, seat: usize) -> Result<()> {
+        match self.session.inner.players.get(seat) {
+            Some(username) if !self.limiter.allow_player(username) => {
+                Err(warp::reject::custom(TooManyRequests))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The state of the game as seen from the given seat
+    fn player_state(&self, seat: usize) -> Result<PlayerGameStateResponse> {
+        let state = &self.session.state;
+        if seat >= state.default_state.players.len() {
+            return Err(warp::reject::not_found());
+        }
+        self.allow_seat(seat)?;
+        Ok(PlayerGameStateResponse::for_seat(state, seat))
+    }
+
+    async fn play(&mut self, action: GameAction) -> Result<()> {
+        self.allow_seat(self.session.state.default_state.turn)?;
+        match action {
+            GameAction::Draw => self.draw().await,
+            GameAction::Discard(card_id) => self.discard(card_id).await,
+            GameAction::Open(meld) => self.meld(meld, HashMap::new(), PlayerMove::Open).await,
+            GameAction::AddPoints(meld) => {
+                let state = &self.session.state;
+                let melded = state
+                    .get_team_index(state.default_state.turn)
+                    .map(|team| state.partners[team].points_deck.clone())
+                    .unwrap_or_default();
+                self.meld(meld, melded, PlayerMove::AddPoints).await
+            }
+            GameAction::PickupDiscard(card_ids) => self.pickup_discard(card_ids).await,
+            GameAction::Undo => self.undo().await,
+        }
+    }
+
+    async fn draw(&mut self) -> Result<()> {
+        let session = &mut self.session;
+        game_action(PlayerMove::Draw, &mut session.state).map_err(|_| warp::reject::reject())?;
+        session.turn_drawn = true;
+        session.record_move();
+        send_state_to_spectators(session, &self.players).await;
+        Ok(())
+    }
+
+    /// Returns the positions of the cards in the hand of the player whose turn it is
+    ///
+    /// ## Returns
+    /// A rejection answering 400 when a card isn't in the hand or is given more than once
+    fn card_indices(&self, card_ids: &[String]) -> Result<Vec<usize>> {
+        let state = &self.session.state;
+        state
+            .card_indices(state.default_state.turn, card_ids)
+            .ok_or_else(|| warp::reject::custom(InvalidCards))
+    }
+
+    async fn discard(&mut self, card_id: String) -> Result<()> {
+        let card_index = self.card_indices(&[card_id])?[0];
+        let session = &mut self.session;
+        let status = game_action(PlayerMove::Discard(card_index), &mut session.state)
+            .map_err(|_| warp::reject::reject())?;
+        handle_game_status(status, session, self.players.clone()).await;
+        session.end_turn();
+
+        let message = WebSocketResponse {
+            response_type: "GameState".into(),
+            data: {},
+        };
+        send_message_to_players(message, session, self.players.clone()).await;
+        bot_player::play_bot_turns(session, self.players.clone()).await;
+        Ok(())
+    }
+
+    /// Opens or adds points with the given cards of the current player's hand
+    ///
+    /// ## Arguments
+    /// `meld` - the cards laid down, grouped by the player or by the rules
+    /// `melded` - the points that let a value be laid down without a complete set
+    /// `player_move` - the move played with the groups of cards
+    async fn meld(
+        &mut self,
+        meld: Meld,
+        melded: HashMap<CardValue, Vec<Card>>,
+        player_move: fn(Vec<MeldGroup>) -> PlayerMove,
+    ) -> Result<()> {
+        // Every card is looked up at once so a card can't be laid down twice
+        let card_ids: Vec<String> = match &meld {
+            Meld::Cards(card_ids) => card_ids.clone(),
+            Meld::Groups(groups) => groups
+                .iter()
+                .flat_map(|group| group.card_ids.iter().cloned())
+                .collect(),
+        };
+        let card_indices = self.card_indices(&card_ids)?;
+
+        let session = &mut self.session;
+        let turn = session.state.default_state.turn;
+        // The cards leave the hand with their identifiers, and are put back
+        // when they can't be laid down
+        let cards = session.state.remove_cards(turn, &card_indices);
+        let groups = match meld {
+            Meld::Cards(_) => MeldGroup::group_cards(&cards, &melded),
+            Meld::Groups(groups) => {
+                let mut remaining = cards.as_slice();
+                groups
+                    .into_iter()
+                    .map(|group| {
+                        let (cards, rest) = remaining.split_at(group.card_ids.len());
+                        remaining = rest;
+                        match group.value {
+                            Some(value) => MeldGroup::new(value, cards.to_vec()),
+                            None => MeldGroup::with_cards(cards.to_vec()),
+                        }
+                    })
+                    .collect()
+            }
+        };
+
+        match game_action(player_move(groups), &mut session.state) {
+            Ok(GameStatus::Active) => {}
+            Err(_) => {
+                session.state.put_back_cards();
+                return Err(warp::reject::reject());
+            }
+            Ok(other_status) => {
+                handle_game_status(other_status, session, self.players.clone()).await
+            }
+        }
+        session.record_move();
+
+        let message = WebSocketResponse {
+            response_type: "GameState".into(),
+            data: {},
+        };
+        send_message_to_players(message, session, self.players.clone()).await;
+        Ok(())
+    }
+
+    /// Suggests melds to the player in the given seat, when the table allows it
+    fn hints(&self, seat: usize) -> Result<Vec<HintResponse>> {
+        let session = &self.session;
+        if !session.inner.hints_allowed {
+            return Err(warp::reject::custom(HintsDisabled));
+        }
+        if seat >= session.state.default_state.players.len() {
+            return Err(warp::reject::not_found());
+        }
+        self.allow_seat(seat)?;
+        let hand_ids = &session.state.card_ids.hands[seat];
+        Ok(suggest_melds(&session.state, seat)
+            .into_iter()
+            .map(|suggestion| HintResponse::new(suggestion, hand_ids))
+            .collect())
+    }
+
+    /// Takes back the last meld of the turn, the cards return to the hand
+    async fn undo(&mut self) -> Result<()> {
+        let session = &mut self.session;
+        if !session.inner.undo_allowed {
+            return Err(warp::reject::custom(UndoDisabled));
+        }
+        game_action(PlayerMove::Undo, &mut session.state).map_err(|_| warp::reject::reject())?;
+        session.record_move();
+
+        let message = WebSocketResponse {
+            response_type: "GameState".into(),
+            data: {},
+        };
+        send_message_to_players(message, session, self.players.clone()).await;
+        Ok(())
+    }
+
+    async fn pickup_discard(&mut self, card_ids: Vec<String>) -> Result<()> {
+        let card_indices = self.card_indices(&card_ids)?;
+        let session = &mut self.session;
+        let turn = session.state.default_state.turn;
+        // The cards are put back in the hand when the pile can't be picked up
+        let cards = session.state.remove_cards(turn, &card_indices);
+
+        match game_action(PlayerMove::TakeDiscardPile(cards), &mut session.state) {
+            Err(_) => {
+                session.state.put_back_cards();
+                return Err(warp::reject::reject());
+            }
+            Ok(GameStatus::Active) => {}
+            Ok(other_status) => {
+                handle_game_status(other_status, session, self.players.clone()).await
+            }
+        }
+        session.record_move();
+
+        let message = WebSocketResponse {
+            response_type: "GameState".into(),
+            data: {},
+        };
+        send_message_to_players(message, session, self.players.clone()).await;
+        Ok(())
+    }
+
+    async fn join(&mut self, username: String) -> Result<GameResponse> {
+        let session = &mut self.session;
+        if session.inner.players.contains(&username) {
+            return Ok(session.inner.clone());
+        }
+        if session.inner.players.len() >= SEATS {
+            return Err(warp::reject::custom(TooManyPlayers));
+        }
+        session
+            .inner
+            .spectators
+            .retain(|spectator| spectator != &username);
+        session.inner.players.push(username.clone());
+        session.touch();
+
+        let mut players = self.players.write().await;
+        if let Some(player) = players.get_mut(&username) {
+            player.inner.game_session_id = Some(session.inner.game_id.clone());
+        }
+        let message = serde_json::to_string(&WebSocketResponse {
+            response_type: "GameSession".into(),
+            data: session.inner.clone(),
+        })
+        .unwrap();
+        // The lobby hears about the new seat through its own channel
+        for username in session
+            .inner
+            .players
+            .iter()
+            .chain(session.inner.spectators.iter())
+        {
+            if let Some(sender) = players
+                .get(username)
+                .and_then(|player| player.sender.as_ref())
+            {
+                // The player may have disconnected in the meantime
+                let _ = sender.send(Ok(Message::text(message.clone())));
+            }
+        }
+
+        Ok(session.inner.clone())
+    }
+
+    async fn spectate(&mut self, username: String) -> Result<GameResponse> {
+        let session = &mut self.session;
+        if session.inner.players.contains(&username) {
+            return Err(warp::reject::custom(AlreadySeated));
+        }
+
+        if let Some(player) = self.players.write().await.get_mut(&username) {
+            player.inner.game_session_id = Some(session.inner.game_id.clone());
+        } else {
+            // Only registered players can watch games
+            return Err(warp::reject::not_found());
+        }
+
+        if !session.inner.spectators.contains(&username) {
+            session.inner.spectators.push(username);
+        }
+        Ok(session.inner.clone())
+    }
+
+    async fn start(&mut self) {
+        let session = &mut self.session;
+        let team_1_points = session.state.partners[0].overall_points;
+        let team_2_points = session.state.partners[1].overall_points;
+
+        session.state =
+            DameDePiqueGameBuilder::initialize_game_with_players(&session.inner.players).unwrap();
+        session.inner.variant.apply(&mut session.state);
+
+        session.state.partners[0].overall_points = team_1_points;
+        session.state.partners[1].overall_points = team_2_points;
+        session.start_round();
+
+        let response_type = if session.is_active {
+            "GameState"
+        } else {
+            "StartGameResponse"
+        };
+        session.is_active = true;
+        let message = serde_json::to_string(&WebSocketResponse {
+            response_type: response_type.into(),
+            data: StartGameResponse {},
+        })
+        .unwrap();
+
+        let recipients = session
+            .inner
+            .players
+            .iter()
+            .chain(session.inner.spectators.iter());
+        send_text_to_players(recipients, &message, &self.players).await;
+
+        bot_player::play_bot_turns(session, self.players.clone()).await;
+    }
+
+    /// Takes a player who unregistered away from the table
+    ///
+    /// ## Rules
+    /// * Seats are only given up before the game starts, the seats of a game
+    ///     in play always match the seats of its state
+    /// * A bot plays the seat of a player who left a game in play
+    async fn leave(&mut self, username: &str) -> bool {
+        let session = &mut self.session;
+        session
+            .inner
+            .spectators
+            .retain(|spectator| spectator != username);
+        let seat = match session.seat_of(username) {
+            Some(seat) => seat,
+            None => return false,
+        };
+        if !session.is_active {
+            session.inner.players.remove(seat);
+            return true;
+        }
+
+        println!("{} left game {}", username, session.inner.game_id);
+        session.disconnected.remove(username);
+        session.bot_seats.insert(seat);
+        let message = WebSocketResponse {
+            response_type: "BotTookSeat".into(),
+            data: SeatStatusResponse {
+                username: username.to_string(),
+                seat,
+                grace_seconds: None,
+            },
+        };
+        send_message_to_players(message, session, self.players.clone()).await;
+        bot_player::play_bot_turns(session, self.players.clone()).await;
+        true
+    }
+
+    async fn disconnect(&mut self, username: String) -> Option<Instant> {
+        let session = &mut self.session;
+        // spectators never hold a seat, they simply stop watching
+        session
+            .inner
+            .spectators
+            .retain(|spectator| spectator != &username);
+        // a player has disconnected and wasn't part of an active game
+        if !session.is_active {
+            session.inner.players.retain(|player| player != &username);
+            self.players.write().await.remove(&username);
+            return None;
+        }
+        let seat = session.seat_of(&username)?;
+
+        let disconnected_at = Instant::now();
+        session
+            .disconnected
+            .insert(username.clone(), disconnected_at);
+
+        let message = WebSocketResponse {
+            response_type: "PlayerDisconnected".into(),
+            data: SeatStatusResponse {
+                username,
+                seat,
+                grace_seconds: Some(self.config.reconnect_grace_seconds),
+            },
+        };
+        send_message_to_players(message, session, self.players.clone()).await;
+        Some(disconnected_at)
+    }
+
+    async fn reconnect(&mut self, username: String) {
+        let session = &mut self.session;
+        if !session.is_active {
+            return;
+        }
+        let seat = match session.seat_of(&username) {
+            Some(seat) => seat,
+            None => return,
+        };
+
+        let was_away = session.disconnected.remove(&username).is_some();
+        session.bot_seats.remove(&seat);
+        if was_away {
+            println!("{} reconnected to game {}", username, session.inner.game_id);
+            let message = WebSocketResponse {
+                response_type: "PlayerReconnected".into(),
+                data: SeatStatusResponse {
+                    username: username.clone(),
+                    seat,
+                    grace_seconds: None,
+                },
+            };
+            send_message_to_players(message, session, self.players.clone()).await;
+        }
+
+        let message = serde_json::to_string(&WebSocketResponse {
+            response_type: "ResumeGame".into(),
+            data: PlayerGameStateResponse::for_seat(&session.state, seat),
+        })
+        .unwrap();
+        send_text_to_players(std::iter::once(&username), &message, &self.players).await;
+    }
+
+    async fn expire_reservation(&mut self, username: String, disconnected_at: Instant) {
+        let session = &mut self.session;
+        // The player reconnected, or disconnected again since
+        if session.disconnected.get(&username) != Some(&disconnected_at) {
+            return;
+        }
+        let seat = match session.seat_of(&username) {
+            Some(seat) => seat,
+            None => return,
+        };
+
+        println!("{}'s seat reservation expired", username);
+        let response_type = if self.config.bot_takeover {
+            session.bot_seats.insert(seat);
+            "BotTookSeat"
+        } else {
+            "SeatReservationExpired"
+        };
+        let message = WebSocketResponse {
+            response_type: response_type.into(),
+            data: SeatStatusResponse {
+                username,
+                seat,
+                grace_seconds: None,
+            },
+        };
+        send_message_to_players(message, session, self.players.clone()).await;
+        bot_player::play_bot_turns(session, self.players.clone()).await;
+    }
+
+    async fn close(&mut self, reason: CloseReason) {
+        let game_id = &self.session.inner.game_id;
+        if self.listed.take().is_some() {
+            self.lobby.publish(LobbyEvent::Removed {
+                game_id: game_id.clone(),
+            });
+        }
+        let message = serde_json::to_string(&WebSocketResponse {
+            response_type: "GameClosed".into(),
+            data: GameClosedResponse {
+                game_id: game_id.clone(),
+                reason: reason.to_string(),
+            },
+        })
+        .unwrap();
+
+        let mut players = self.players.write().await;
+        for username in self
+            .session
+            .inner
+            .players
+            .iter()
+            .chain(self.session.inner.spectators.iter())
+        {
+            if let Some(player) = players.get_mut(username) {
+                if player.inner.game_session_id.as_ref() != Some(game_id) {
+                    continue;
+                }
+                player.inner.game_session_id = None;
+                if let Some(sender) = &player.sender {
+                    // The player may have disconnected in the meantime
+                    let _ = sender.send(Ok(Message::text(message.clone())));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::gameplay::gameplay_handlers::draw_card_handler;
+    use crate::gameplay::gameplay_models::GameQuery;
+    use crate::models::{GameSessions, Player, PlayerResponse, RuleVariant};
+    use crate::rate_limit::RateLimiter;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    const TABLES: usize = 50;
+
+    fn new_table(game_id: &str, players: &Players) -> GameHandle {
+        let session = GameSession::new(
+            game_id.to_string(),
+            game_id.to_uppercase(),
+            false,
+            RuleVariant::Classic,
+            Vec::new(),
+            None,
+            None,
+        );
+        GameHandle::spawn(
+            session,
+            players.clone(),
+            Arc::new(Config::default()),
+            Arc::new(RateLimiter::from(&Config::default())),
+            Lobby::new(),
+        )
+    }
+
+    #[tokio::test]
+    async fn tables_do_not_wait_on_each_other() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let sessions: GameSessions = Arc::new(RwLock::new(HashMap::new()));
+        for table in 1..TABLES {
+            let game_id = format!("table-{}", table);
+            let game = new_table(&game_id, &players);
+            sessions.write().await.insert(game_id, game);
+        }
+
+        // A stalled table never gets around to handling its commands
+        let (sender, _stalled_commands) = mpsc::unbounded_channel();
+        sessions.write().await.insert(
+            "table-0".into(),
+            GameHandle {
+                host: String::new(),
+                invite_code: String::new(),
+                private: false,
+                sender,
+            },
+        );
+        let query = GameQuery {
+            game_id: "table-0".into(),
+        };
+        tokio::task::spawn(draw_card_handler(query, sessions.clone()));
+
+        let moves = (1..TABLES).map(|table| {
+            let query = GameQuery {
+                game_id: format!("table-{}", table),
+            };
+            draw_card_handler(query, sessions.clone())
+        });
+        let results =
+            tokio::time::timeout(Duration::from_secs(5), futures::future::join_all(moves))
+                .await
+                .expect("tables were blocked by another table");
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        for table in 1..TABLES {
+            let game = sessions.read().await[&format!("table-{}", table)].clone();
+            let session = game
+                .request(|reply| GameCommand::Snapshot { reply })
+                .await
+                .unwrap();
+            assert!(session.turn_drawn);
+        }
+    }
+
+    type Messages = mpsc::UnboundedReceiver<std::result::Result<Message, warp::Error>>;
+
+    /// Registers a connected player, returns the messages sent to their websocket
+    async fn register(players: &Players, username: &str) -> Messages {
+        let (sender, messages) = mpsc::unbounded_channel();
+        players.write().await.insert(
+            username.to_string(),
+            Player {
+                sender: Some(sender),
+                connection: 1,
+                inner: PlayerResponse {
+                    username: username.to_string(),
+                    game_session_id: None,
+                    websocket_url: String::new(),
+                },
+            },
+        );
+        messages
+    }
+
+    /// Returns the response types of the messages received so far
+    fn received(messages: &mut Messages) -> Vec<String> {
+        let mut response_types = vec![];
+        while let Ok(message) = messages.try_recv() {
+            let message: serde_json::Value =
+                serde_json::from_str(message.unwrap().to_str().unwrap()).unwrap();
+            response_types.push(message["response_type"].as_str().unwrap().to_string());
+        }
+        response_types
+    }
+
+    async fn snapshot(game: &GameHandle) -> GameSession {
+        game.request(|reply| GameCommand::Snapshot { reply })
+            .await
+            .unwrap()
+    }
+
+    async fn join(game: &GameHandle, username: &str) -> Result<GameResponse> {
+        let username = username.to_string();
+        game.request(|reply| GameCommand::Join { username, reply })
+            .await
+            .unwrap()
+    }
+
+    async fn spectate(game: &GameHandle, username: &str) -> Result<GameResponse> {
+        let username = username.to_string();
+        game.request(|reply| GameCommand::Spectate { username, reply })
+            .await
+            .unwrap()
+    }
+
+    async fn leave(game: &GameHandle, username: &str) -> bool {
+        let username = username.to_string();
+        game.request(|reply| GameCommand::Leave { username, reply })
+            .await
+            .unwrap()
+    }
+
+    async fn disconnect(game: &GameHandle, username: &str) -> Option<Instant> {
+        let username = username.to_string();
+        game.request(|reply| GameCommand::Disconnect { username, reply })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn players_join_until_the_table_is_full() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let game = new_table("table", &players);
+        for username in &["north", "east", "south", "west", "late"] {
+            register(&players, username).await;
+        }
+
+        for username in &["north", "east", "south", "west"] {
+            join(&game, username).await.unwrap();
+        }
+        let rejection = join(&game, "late").await.unwrap_err();
+        assert!(rejection.find::<TooManyPlayers>().is_some());
+        // Joining again keeps the player in their seat
+        let table = join(&game, "east").await.unwrap();
+        assert_eq!(table.players, vec!["north", "east", "south", "west"]);
+        assert_eq!(
+            players.read().await["east"]
+                .inner
+                .game_session_id
+                .as_deref(),
+            Some("table")
+        );
+        assert_eq!(players.read().await["late"].inner.game_session_id, None);
+    }
+
+    #[tokio::test]
+    async fn players_leave_before_the_game_starts() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let game = new_table("table", &players);
+        for username in &["north", "east", "south"] {
+            register(&players, username).await;
+            join(&game, username).await.unwrap();
+        }
+
+        assert!(leave(&game, "east").await);
+        assert!(!leave(&game, "east").await);
+        // Disconnecting before the game starts gives up the seat too
+        assert_eq!(disconnect(&game, "south").await, None);
+        assert!(!players.read().await.contains_key("south"));
+
+        let session = snapshot(&game).await;
+        assert_eq!(session.inner.players, vec!["north"]);
+    }
+
+    #[tokio::test]
+    async fn disconnected_players_reclaim_their_seat() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let game = started_table(&players).await;
+        let mut east = register(&players, "east").await;
+
+        let disconnected_at = disconnect(&game, "east").await.unwrap();
+        let session = snapshot(&game).await;
+        assert_eq!(session.disconnected.get("east"), Some(&disconnected_at));
+        assert_eq!(session.inner.players[1], "east");
+
+        game.notify(GameCommand::Reconnect {
+            username: "east".into(),
+        });
+        let session = snapshot(&game).await;
+        assert!(session.disconnected.is_empty());
+        assert!(session.bot_seats.is_empty());
+        assert!(received(&mut east).contains(&"ResumeGame".to_string()));
+
+        // The reservation of a player who came back doesn't expire
+        game.notify(GameCommand::ReservationExpired {
+            username: "east".into(),
+            disconnected_at,
+        });
+        assert!(snapshot(&game).await.bot_seats.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_reservations_hand_the_seat_to_a_bot() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let config = Config {
+            bot_takeover: true,
+            ..Config::default()
+        };
+        let game = started_table_with(&players, config).await;
+        let mut north = register(&players, "north").await;
+
+        let disconnected_at = disconnect(&game, "east").await.unwrap();
+        // Only the reservation of the last disconnection expires
+        game.notify(GameCommand::ReservationExpired {
+            username: "east".into(),
+            disconnected_at: Instant::now(),
+        });
+        assert!(snapshot(&game).await.bot_seats.is_empty());
+
+        game.notify(GameCommand::ReservationExpired {
+            username: "east".into(),
+            disconnected_at,
+        });
+        let session = snapshot(&game).await;
+        assert!(session.bot_seats.contains(&1));
+        assert!(received(&mut north).contains(&"BotTookSeat".to_string()));
+
+        // The bot plays east's turn right after north's
+        play_turn(&game).await;
+        let session = snapshot(&game).await;
+        assert_eq!(session.state.default_state.turn, 2);
+        assert_eq!(session.inner.players[1], "east");
+    }
+
+    #[tokio::test]
+    async fn spectators_watch_without_a_seat() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let game = new_table("table", &players);
+        for username in &["north", "west"] {
+            register(&players, username).await;
+        }
+        join(&game, "north").await.unwrap();
+
+        let rejection = spectate(&game, "north").await.unwrap_err();
+        assert!(rejection.find::<AlreadySeated>().is_some());
+        // Only registered players can watch
+        assert!(spectate(&game, "ghost").await.unwrap_err().is_not_found());
+
+        spectate(&game, "west").await.unwrap();
+        let table = spectate(&game, "west").await.unwrap();
+        assert_eq!(table.spectators, vec!["west"]);
+        assert_eq!(
+            players.read().await["west"]
+                .inner
+                .game_session_id
+                .as_deref(),
+            Some("table")
+        );
+
+        // A spectator taking a seat stops watching
+        let table = join(&game, "west").await.unwrap();
+        assert_eq!(table.players, vec!["north", "west"]);
+        assert!(table.spectators.is_empty());
+    }
+
+    /// Starts a game with a player in every seat
+    async fn started_table(players: &Players) -> GameHandle {
+        started_table_with(players, Config::default()).await
+    }
+
+    /// Starts a game with a player in every seat under the given configuration
+    async fn started_table_with(players: &Players, config: Config) -> GameHandle {
+        let session = GameSession::new(
+            "table".into(),
+            "TABLE".into(),
+            false,
+            RuleVariant::Classic,
+            vec!["north".into(), "east".into(), "south".into(), "west".into()],
+            None,
+            None,
+        );
+        let limiter = Arc::new(RateLimiter::from(&config));
+        let game = GameHandle::spawn(
+            session,
+            players.clone(),
+            Arc::new(config),
+            limiter,
+            Lobby::new(),
+        );
+        game.request(|reply| GameCommand::Start { reply }).await;
+        game
+    }
+
+    /// Draws and discards the first card of the hand of the player whose turn it is
+    async fn play_turn(game: &GameHandle) {
+        let session = game
+            .request(|reply| GameCommand::Snapshot { reply })
+            .await
+            .unwrap();
+        let turn = session.state.default_state.turn;
+        let card_id = session.state.card_ids.hands[turn][0].to_string();
+        game.request(|reply| GameCommand::Play {
+            action: GameAction::Draw,
+            reply,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        game.request(|reply| GameCommand::Play {
+            action: GameAction::Discard(card_id),
+            reply,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn players_keep_their_seat_when_another_leaves() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let game = started_table(&players).await;
+        let left = game
+            .request(|reply| GameCommand::Leave {
+                username: "east".into(),
+                reply,
+            })
+            .await;
+        assert_eq!(left, Some(true));
+
+        // North plays, then the bot plays east's turn
+        play_turn(&game).await;
+        let session = game
+            .request(|reply| GameCommand::Snapshot { reply })
+            .await
+            .unwrap();
+        assert_eq!(session.seat_of("east"), Some(1));
+        assert!(session.bot_seats.contains(&1));
+        assert_eq!(session.seat_of("south"), Some(2));
+        assert_eq!(session.state.default_state.turn, 2);
+
+        // South still plays as seat 2
+        play_turn(&game).await;
+        let session = game
+            .request(|reply| GameCommand::Snapshot { reply })
+            .await
+            .unwrap();
+        assert_eq!(session.state.default_state.turn, 3);
+        assert_eq!(session.state.card_ids.hands[2].len(), 13);
+    }
+
+    #[tokio::test]
+    async fn moves_are_played_in_order() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let game = new_table("table", &players);
+        let session = game
+            .request(|reply| GameCommand::Snapshot { reply })
+            .await
+            .unwrap();
+        let turn = session.state.default_state.turn;
+        let card_id = session.state.card_ids.hands[turn][0].to_string();
+
+        let (draw, _) = oneshot::channel();
+        let (discard, _) = oneshot::channel();
+        game.notify(GameCommand::Play {
+            action: GameAction::Draw,
+            reply: draw,
+        });
+        game.notify(GameCommand::Play {
+            action: GameAction::Discard(card_id),
+            reply: discard,
+        });
+
+        let session = game
+            .request(|reply| GameCommand::Snapshot { reply })
+            .await
+            .unwrap();
+        assert_eq!(session.moves_played, 2);
+        assert_eq!(session.state.default_state.turn, 1);
+        assert!(!session.turn_drawn);
+    }
+
+    #[tokio::test]
+    async fn requests_count_against_the_player_in_the_seat() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let config = Config {
+            player_requests_per_window: 1,
+            ..Config::default()
+        };
+        let game = started_table_with(&players, config).await;
+
+        game.request(|reply| GameCommand::Play {
+            action: GameAction::Draw,
+            reply,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        let rejection = game
+            .request(|reply| GameCommand::Play {
+                action: GameAction::Undo,
+                reply,
+            })
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(rejection.find::<TooManyRequests>().is_some());
+        let rejection = game
+            .request(|reply| GameCommand::PlayerState { seat: 0, reply })
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(rejection.find::<TooManyRequests>().is_some());
+
+        // The other players have requests of their own left
+        let state = game
+            .request(|reply| GameCommand::PlayerState { seat: 1, reply })
+            .await
+            .unwrap();
+        assert!(state.is_ok());
+    }
+}
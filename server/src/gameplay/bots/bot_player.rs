@@ -0,0 +1,78 @@
+use crate::gameplay::gameplay_handlers::{
+    game_action, handle_game_status, send_message_to_players,
+};
+use crate::models::{GameSession, Players, WebSocketResponse};
+use game::error::DameDePiqueError;
+use game::gameplay::PlayerMove;
+use game::rules::GameStatus;
+
+/// Plays the safest possible turn for the player whose turn it is
+///
+/// ## Purpose
+/// Draws a card if the player hasn't drawn yet this turn, then discards
+/// the card that would cost the most points at the end of the round
+///
+/// ## Arguments
+/// `session` - the game session being played
+/// `players` - persistent collection of players
+///
+/// ## Returns
+/// Whether the round is still active after the turn
+pub async fn play_default_turn(
+    session: &mut GameSession,
+    players: Players,
+) -> Result<bool, DameDePiqueError> {
+    if !session.turn_drawn {
+        game_action(PlayerMove::Draw, &mut session.state)?;
+        session.turn_drawn = true;
+        session.record_move();
+    }
+
+    let turn = session.state.default_state.turn;
+    let card_index = match PlayerMove::highest_debt_card_index(
+        &session.state.default_state.players[turn].hand,
+    ) {
+        Some(i) => i,
+        // An empty hand means the round is already over
+        None => return Ok(false),
+    };
+
+    let status = game_action(PlayerMove::Discard(card_index), &mut session.state)?;
+    let round_active = matches!(status, GameStatus::Active);
+    handle_game_status(status, session, players.clone()).await;
+    session.end_turn();
+
+    let message = WebSocketResponse {
+        response_type: "GameState".into(),
+        data: {},
+    };
+    send_message_to_players(message, session, players).await;
+    Ok(round_active)
+}
+
+/// Plays the turns of every seat controlled by a bot until
+/// it is a connected player's turn or the round ends
+///
+/// ## Arguments
+/// `session` - the game session being played
+/// `players` - persistent collection of players
+pub async fn play_bot_turns(session: &mut GameSession, players: Players) {
+    while session.is_active
+        && session
+            .bot_seats
+            .contains(&session.state.default_state.turn)
+    {
+        println!(
+            "Bot playing for seat {} in game {}",
+            session.state.default_state.turn, session.inner.game_id
+        );
+        match play_default_turn(session, players.clone()).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                println!("The bot could not play its turn: {}", e);
+                break;
+            }
+        }
+    }
+}
@@ -1,202 +1,211 @@
+use crate::gameplay::game_actor::{CardGroup, GameAction, GameCommand, Meld};
 use crate::gameplay::gameplay_models::{
-    GameDiscardRequest, PlayerAddPointsRequest, PlayerGameStateResponse, PlayerOpenRequest,
-    PlayerPickupDiscardRequest,
+    card_value, GameDiscardRequest, GameQuery, MeldGroupRequest, PlayerAddPointsRequest,
+    PlayerOpenRequest, PlayerPickupDiscardRequest, PlayerUndoRequest, RoundSummaryResponse,
+    SeatQuery, SpectatorGameStateResponse,
+};
+use crate::metrics;
+use crate::models::{
+    find_session, GameSession, GameSessions, InvalidCards, Players, WebSocketResponse,
 };
-use crate::models::{GameSession, GameSessions, Players, WebSocketResponse};
 use crate::Result;
-use game::gameplay::DameDePiqueGameBuilder;
-use game::gameplay::PlayerMove;
-use game::rules::{GameRules, GameStatus};
-use game::state::GameState;
+use game::error::DameDePiqueError;
+use game::gameplay::{DDPState, DameDePiqueGameBuilder, PlayerMove};
+use game::rules::GameStatus;
 use game::Game;
 use serde::Serialize;
-use std::collections::HashMap;
 use warp::http::StatusCode;
 use warp::reply::{json, Reply};
 use warp::ws::Message;
 
 type DameDePiqueGame = Game<DameDePiqueGameBuilder, PlayerMove>;
 
+/// Handler for the state of the game as seen by a player
+///
+/// ## Arguments
+/// `query` - the game, as `game-id`, and the seat of the player, as `player`
+/// `sessions` - persistent collection of game sessions
 pub async fn get_game_state_handler(
-    params: HashMap<String, String>,
+    query: SeatQuery,
     sessions: GameSessions,
 ) -> Result<impl Reply> {
     println!("Gameplay Request");
-    if let Some(game) = sessions.read().await.get(&params["game-id"]) {
-        let player = &params["player"].parse::<usize>().unwrap();
-        let mut response: PlayerGameStateResponse = game.state.clone().into();
-        response.player_hand = game.state.default_state.players[*player]
-            .hand
-            .iter()
-            .map(|v| crate::gameplay::gameplay_models::Card::from(v.clone()))
-            .collect();
-        Ok(json(&response))
-    } else {
-        Err(warp::reject::not_found())
-    }
+    let game = find_session(&sessions, &query.game_id)
+        .await
+        .ok_or_else(warp::reject::not_found)?;
+    let seat = query.player;
+    let state = game
+        .request(|reply| GameCommand::PlayerState { seat, reply })
+        .await
+        .unwrap_or_else(|| Err(warp::reject::not_found()))?;
+    Ok(json(&state))
 }
 
-pub async fn draw_card_handler(
-    params: HashMap<String, String>,
+/// Handler for the public state of the game
+///
+/// ## Arguments
+/// `query` - the game, as `game-id`
+/// `sessions` - persistent collection of game sessions
+pub async fn get_spectator_state_handler(
+    query: GameQuery,
     sessions: GameSessions,
 ) -> Result<impl Reply> {
-    println!("Draw Card Request");
-    if let Some(game) = sessions.write().await.get_mut(&params["game-id"]) {
-        DameDePiqueGame::game_action(PlayerMove::Draw, &mut game.state).unwrap();
-        Ok(StatusCode::OK)
-    } else {
-        Err(warp::reject::not_found())
+    println!("Spectator Request");
+    let game = find_session(&sessions, &query.game_id)
+        .await
+        .ok_or_else(warp::reject::not_found)?;
+    match game
+        .request(|reply| GameCommand::SpectatorState { reply })
+        .await
+    {
+        Some(state) => Ok(json(&state)),
+        None => Err(warp::reject::not_found()),
     }
 }
 
+/// Handler for the meld suggestions of a player
+///
+/// ## Arguments
+/// `query` - the game, as `game-id`, and the seat of the player, as `player`
+/// `sessions` - persistent collection of game sessions
+pub async fn get_hints_handler(query: SeatQuery, sessions: GameSessions) -> Result<impl Reply> {
+    println!("Hints Request");
+    let game = find_session(&sessions, &query.game_id)
+        .await
+        .ok_or_else(warp::reject::not_found)?;
+    let seat = query.player;
+    match game
+        .request(|reply| GameCommand::Hints { seat, reply })
+        .await
+    {
+        Some(hints) => Ok(json(&hints?)),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+pub async fn draw_card_handler(query: GameQuery, sessions: GameSessions) -> Result<impl Reply> {
+    println!("Draw Card Request");
+    play_move(&sessions, &query.game_id, GameAction::Draw).await?;
+    Ok(StatusCode::OK)
+}
+
 pub async fn discard_handler(
     request: GameDiscardRequest,
-    players: Players,
     sessions: GameSessions,
 ) -> Result<impl Reply> {
     println!("End Turn Request");
-    if let Some(game) = sessions.write().await.get_mut(&request.game_id) {
-        handle_game_status(
-            DameDePiqueGame::game_action(PlayerMove::Discard(request.card_index), &mut game.state)
-                .unwrap(),
-            game,
-            players.clone(),
-        )
-        .await;
-        PlayerMove::end_turn(&mut game.state);
-
-        let message = WebSocketResponse {
-            response_type: "GameState".into(),
-            data: {},
-        };
-        send_message_to_players(message, game, players).await;
-        Ok(StatusCode::OK)
-    } else {
-        Err(warp::reject::not_found())
-    }
+    play_move(
+        &sessions,
+        &request.game_id,
+        GameAction::Discard(request.card_id),
+    )
+    .await?;
+    Ok(StatusCode::OK)
 }
 
 pub async fn player_open_handler(
     request: PlayerOpenRequest,
-    players: Players,
     sessions: GameSessions,
 ) -> Result<impl Reply> {
     println!("Player Open Request");
-    if let Some(game) = sessions.write().await.get_mut(&request.game_id) {
-        let hand = game.state.default_state.players[game.state.default_state.turn]
-            .hand
-            .iter();
-        let cards = hand
-            .enumerate()
-            .filter(|(i, _c)| request.card_indices.contains(i))
-            .map(|c| c.1.clone())
-            .collect::<Vec<game::models::Card>>();
-        match DameDePiqueGame::game_action(PlayerMove::Open(cards), &mut game.state) {
-            Ok(GameStatus::Active) => {
-                for i in request.card_indices.iter().rev() {
-                    game.state.default_state.players[game.state.default_state.turn]
-                        .hand
-                        .remove(*i);
-                }
-            }
-            Err(_) => return Err(warp::reject::reject()),
-            Ok(other_status) => handle_game_status(other_status, game, players.clone()).await,
-        }
-
-        let message = WebSocketResponse {
-            response_type: "GameState".into(),
-            data: {},
-        };
-        send_message_to_players(message, game, players).await;
-        Ok(StatusCode::OK)
-    } else {
-        Err(warp::reject::not_found())
-    }
+    let meld = to_meld(request.card_ids, request.groups)?;
+    play_move(&sessions, &request.game_id, GameAction::Open(meld)).await?;
+    Ok(StatusCode::OK)
 }
 
 pub async fn player_add_points_handler(
     request: PlayerAddPointsRequest,
-    players: Players,
     sessions: GameSessions,
 ) -> Result<impl Reply> {
-    println!("Player Open Request");
-    if let Some(game) = sessions.write().await.get_mut(&request.game_id) {
-        let hand = game.state.default_state.players[game.state.default_state.turn]
-            .hand
-            .iter();
-        let cards = hand
-            .enumerate()
-            .filter(|(i, _c)| request.card_indices.contains(i))
-            .map(|c| c.1.clone())
-            .collect::<Vec<game::models::Card>>();
-        match DameDePiqueGame::game_action(PlayerMove::AddPoints(cards), &mut game.state) {
-            Ok(GameStatus::Active) => {
-                for i in request.card_indices.iter().rev() {
-                    game.state.default_state.players[game.state.default_state.turn]
-                        .hand
-                        .remove(*i);
-                }
-            }
-            Err(_) => return Err(warp::reject::reject()),
-            Ok(other_status) => handle_game_status(other_status, game, players.clone()).await,
-        }
-
-        let message = WebSocketResponse {
-            response_type: "GameState".into(),
-            data: {},
-        };
-        send_message_to_players(message, game, players).await;
-        Ok(StatusCode::OK)
-    } else {
-        Err(warp::reject::not_found())
-    }
+    println!("Player Add Points Request");
+    let meld = to_meld(request.card_ids, request.groups)?;
+    play_move(&sessions, &request.game_id, GameAction::AddPoints(meld)).await?;
+    Ok(StatusCode::OK)
 }
 
 pub async fn player_pickup_discard_handler(
     request: PlayerPickupDiscardRequest,
-    players: Players,
     sessions: GameSessions,
 ) -> Result<impl Reply> {
     println!("Player Pickup Discard Request");
-    if let Some(game) = sessions.write().await.get_mut(&request.game_id) {
-        let mut cards = Vec::new();
-        for i in request.card_indices.iter().rev() {
-            cards.push(
-                game.state.default_state.players[game.state.default_state.turn]
-                    .hand
-                    .remove(*i),
-            );
-        }
-
-        match DameDePiqueGame::game_action(
-            PlayerMove::TakeDiscardPile(cards.clone()),
-            &mut game.state,
-        ) {
-            Err(_) => {
-                cards.iter().for_each(|c| {
-                    game.state.default_state.players[game.state.default_state.turn]
-                        .hand
-                        .push(c.clone())
-                });
-                game.state.default_state.players[game.state.default_state.turn]
-                    .hand
-                    .sort();
-                return Err(warp::reject::reject());
-            }
-            Ok(GameStatus::Active) => {}
-            Ok(other_status) => handle_game_status(other_status, game, players.clone()).await,
-        }
+    play_move(
+        &sessions,
+        &request.game_id,
+        GameAction::PickupDiscard(request.card_ids),
+    )
+    .await?;
+    Ok(StatusCode::OK)
+}
 
-        let message = WebSocketResponse {
-            response_type: "GameState".into(),
-            data: {},
-        };
-        send_message_to_players(message, game, players).await;
+/// Handler for the current player taking back their last meld of the turn
+///
+/// ## Arguments
+/// `request` - the game the meld was laid down in
+/// `sessions` - persistent collection of game sessions
+pub async fn player_undo_handler(
+    request: PlayerUndoRequest,
+    sessions: GameSessions,
+) -> Result<impl Reply> {
+    println!("Player Undo Request");
+    play_move(&sessions, &request.game_id, GameAction::Undo).await?;
+    Ok(StatusCode::OK)
+}
 
-        Ok(StatusCode::OK)
-    } else {
-        Err(warp::reject::not_found())
+/// Reads the cards laid down by the player, the player's grouping is kept when given
+///
+/// ## Arguments
+/// `card_ids` - the cards of the hand to be grouped by the rules
+/// `groups` - the cards of the hand grouped by the player
+fn to_meld(card_ids: Vec<String>, groups: Vec<MeldGroupRequest>) -> Result<Meld> {
+    if groups.is_empty() {
+        return Ok(Meld::Cards(card_ids));
     }
+    groups
+        .into_iter()
+        .map(|group| {
+            let value = match group.value {
+                Some(name) => {
+                    Some(card_value(&name).ok_or_else(|| warp::reject::custom(InvalidCards))?)
+                }
+                None => None,
+            };
+            Ok(CardGroup {
+                value,
+                card_ids: group.card_ids,
+            })
+        })
+        .collect::<Result<Vec<CardGroup>>>()
+        .map(Meld::Groups)
+}
+
+/// Sends a move to the game session and waits for it to be played
+///
+/// ## Arguments
+/// `sessions` - persistent collection of game sessions
+/// `game_id` - the game session the move is played in
+/// `action` - the move played by the player whose turn it is
+async fn play_move(sessions: &GameSessions, game_id: &str, action: GameAction) -> Result<()> {
+    let game = find_session(sessions, game_id)
+        .await
+        .ok_or_else(warp::reject::not_found)?;
+    game.request(|reply| GameCommand::Play { action, reply })
+        .await
+        .unwrap_or_else(|| Err(warp::reject::not_found()))
+}
+
+/// Plays a move against the rules of the game and counts it in the metrics
+///
+/// ## Arguments
+/// `player_move` - the move played by the player whose turn it is
+/// `state` - the state of the game the move is played in
+pub fn game_action(
+    player_move: PlayerMove,
+    state: &mut DDPState,
+) -> std::result::Result<GameStatus, DameDePiqueError> {
+    let kind = metrics::move_kind(&player_move);
+    let result = DameDePiqueGame::game_action(player_move, state);
+    metrics::record_move(kind, &result);
+    result
 }
 
 pub async fn handle_game_status<'a>(
@@ -206,6 +215,8 @@ pub async fn handle_game_status<'a>(
 ) {
     match status {
         GameStatus::GameOver => {
+            session.round_over = true;
+            session.finished = true;
             let message = WebSocketResponse {
                 response_type: "EndGame".into(),
                 data: {},
@@ -214,9 +225,10 @@ pub async fn handle_game_status<'a>(
         }
         GameStatus::RoundOver => {
             println!("Round Ended");
+            session.round_over = true;
             let message = WebSocketResponse {
                 response_type: "EndRound".into(),
-                data: {},
+                data: RoundSummaryResponse::for_state(&session.state),
             };
             send_message_to_players(message, session, players).await;
         }
@@ -229,15 +241,65 @@ pub async fn send_message_to_players<T: Serialize>(
     session: &mut GameSession,
     players: Players,
 ) {
-    let players = players.read().await;
+    let message = serde_json::to_string(&message).unwrap();
+    send_text_to_players(session.inner.players.iter(), &message, &players).await;
+    send_state_to_spectators(session, &players).await;
+}
 
-    session.inner.players.iter().for_each(|player_id: &String| {
-        if let Some(player) = players.get(player_id) {
+/// Pushes the public state of the table to every spectator
+///
+/// ## Arguments
+/// `session` - the game session being watched
+/// `players` - the collection of players, including spectators
+pub async fn send_state_to_spectators(session: &GameSession, players: &Players) {
+    if session.inner.spectators.is_empty() {
+        return;
+    }
+    let message = serde_json::to_string(&WebSocketResponse {
+        response_type: "SpectatorState".into(),
+        data: SpectatorGameStateResponse::from(session),
+    })
+    .unwrap();
+    send_text_to_players(session.inner.spectators.iter(), &message, players).await;
+}
+
+/// Sends a message to the websocket of every given player
+///
+/// ## Purpose
+/// The websocket of a player may have closed without the player being
+/// disconnected yet, its sender is dropped instead of failing the table
+///
+/// ## Arguments
+/// `player_ids` - the players the message is sent to
+/// `message` - the text of the message
+/// `players` - the collection of players
+pub async fn send_text_to_players<'a>(
+    player_ids: impl Iterator<Item = &'a String>,
+    message: &str,
+    players: &Players,
+) {
+    let mut closed = Vec::new();
+    for player_id in player_ids {
+        if let Some(player) = players.read().await.get(player_id) {
             if let Some(sender) = &player.sender {
-                sender
-                    .send(Ok(Message::text(serde_json::to_string(&message).unwrap())))
-                    .unwrap();
+                if sender.send(Ok(Message::text(message))).is_err() {
+                    closed.push((player_id.clone(), player.connection));
+                }
             }
         }
-    });
+    }
+    if closed.is_empty() {
+        return;
+    }
+
+    let mut players = players.write().await;
+    for (player_id, connection) in closed {
+        println!("{} can't be sent messages anymore", player_id);
+        if let Some(player) = players.get_mut(&player_id) {
+            // The player may have connected again in the meantime
+            if player.connection == connection {
+                player.sender = None;
+            }
+        }
+    }
 }
@@ -1,5 +1,11 @@
+mod actors;
+mod bots;
 mod handlers;
 mod models;
+mod timers;
 
+pub use actors::game_actor;
+pub use bots::bot_player;
 pub use handlers::gameplay_handlers;
 pub use models::gameplay_models;
+pub use timers::turn_timer;
@@ -1,5 +1,8 @@
-use game::gameplay::DDPState;
+use crate::models::{GameResponse, GameSession};
+use game::cards::CardId;
+use game::gameplay::{DDPState, QueenOfSpadesMeld};
 use game::models::CardValue;
+use game::suggestions::{Suggestion, SuggestionKind};
 use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
@@ -7,25 +10,66 @@ use std::collections::HashMap;
 #[derive(Clone, Debug, Deserialize)]
 pub struct GameDiscardRequest {
     pub game_id: String,
-    pub card_index: usize,
+    pub card_id: String,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct PlayerPickupDiscardRequest {
     pub game_id: String,
-    pub card_indices: Vec<usize>,
+    pub card_ids: Vec<String>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlayerUndoRequest {
+    pub game_id: String,
+}
+
+/// The game a request is about, a missing field answers 400
+#[derive(Clone, Debug, Deserialize)]
+pub struct GameQuery {
+    #[serde(rename = "game-id")]
+    pub game_id: String,
+}
+
+/// The player a request is about, a missing or invalid field answers 400
+#[derive(Clone, Debug, Deserialize)]
+pub struct SeatQuery {
+    #[serde(rename = "game-id")]
+    pub game_id: String,
+    /// The seat of the player
+    pub player: usize,
+}
+
+/// Cards of the hand laid down as the points of a single value
+#[derive(Clone, Debug, Deserialize)]
+pub struct MeldGroupRequest {
+    /// The value the group is filed under, as in `Card`,
+    /// taken from its cards when missing
+    #[serde(default)]
+    pub value: Option<String>,
+    pub card_ids: Vec<String>,
+}
+
+/// Cards are either listed in `card_ids` and grouped by the rules,
+/// or grouped by the player in `groups`
 #[derive(Clone, Debug, Deserialize)]
 pub struct PlayerOpenRequest {
     pub game_id: String,
-    pub card_indices: Vec<usize>,
+    #[serde(default)]
+    pub card_ids: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<MeldGroupRequest>,
 }
 
+/// Cards are either listed in `card_ids` and grouped by the rules,
+/// or grouped by the player in `groups`
 #[derive(Clone, Debug, Deserialize)]
 pub struct PlayerAddPointsRequest {
     pub game_id: String,
-    pub card_indices: Vec<usize>,
+    #[serde(default)]
+    pub card_ids: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<MeldGroupRequest>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -36,46 +80,85 @@ pub struct PlayerGameStateResponse {
     team_1_total_points: i16,
     team_2_total_points: i16,
     top_discard: Option<Card>,
+    /// Whether a natural pair of the top card is required to pick up the discard pile
+    discard_pile_frozen: bool,
     turn: usize,
+    seats: Vec<SeatResponse>,
+}
+
+/// Public information about a player seated at the table
+#[derive(Clone, Debug, Serialize)]
+pub struct SeatResponse {
+    seat: usize,
+    username: String,
+    team: usize,
+    hand_count: usize,
+    has_opened: bool,
+}
+
+/// Public view of the table sent to spectators
+///
+/// Hands are only revealed when the table allows coaching,
+/// and only as they were a number of moves ago
+#[derive(Clone, Debug, Serialize)]
+pub struct SpectatorGameStateResponse {
+    state: PlayerGameStateResponse,
+    moves_played: usize,
+    revealed_hands: Option<Vec<Vec<Card>>>,
+}
+
+/// Everything needed to look into a game session after the server stopped
+#[derive(Clone, Debug, Serialize)]
+pub struct GameSessionSnapshot {
+    game: GameResponse,
+    is_active: bool,
+    finished: bool,
+    moves_played: usize,
+    bot_seats: Vec<usize>,
+    state: PlayerGameStateResponse,
+    hands: Vec<Vec<Card>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Card {
     suit: String,
     value: String,
+    /// Identifies the card wherever it is on the table, cards of the
+    /// player's hand are given by it in move requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// The deck of the shoe the card comes from, given along with `id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deck: Option<u8>,
 }
 
 impl From<DDPState> for PlayerGameStateResponse {
     fn from(other: DDPState) -> PlayerGameStateResponse {
-        let team1_points: HashMap<String, Vec<Card>> = other.partners[0]
-            .points_deck
-            .clone()
-            .into_iter()
-            .map(|(k, v)| {
-                (
-                    format!("{:?}", k),
-                    v.iter().map(|bb| Card::from(bb.clone())).collect(),
-                )
-            })
-            .collect();
-
-        let team2_points: HashMap<String, Vec<Card>> = other.partners[1]
-            .points_deck
-            .clone()
-            .into_iter()
-            .map(|(k, v)| {
-                (
-                    format!("{:?}", k),
-                    v.iter().map(|bb| Card::from(bb.clone())).collect(),
-                )
-            })
-            .collect();
+        let team1_points = team_points(&other, 0);
+        let team2_points = team_points(&other, 1);
 
         let team_1_total_points = other.partners[0].overall_points;
         let team_2_total_points = other.partners[1].overall_points;
 
+        let seats = other
+            .default_state
+            .players
+            .iter()
+            .enumerate()
+            .map(|(seat, player)| SeatResponse {
+                seat,
+                username: player.name.clone(),
+                team: other.get_team_index(seat).unwrap_or_default(),
+                hand_count: player.hand.len(),
+                has_opened: other.player_has_opened(seat),
+            })
+            .collect();
+
         let top_discard = match other.default_state.deck.peek_top_discarded_card() {
-            Some(card) => Some(Card::from(card.clone())),
+            Some(card) => Some(Card::with_id(
+                card.clone(),
+                other.card_ids.discard_pile.last(),
+            )),
             None => None,
         };
         PlayerGameStateResponse {
@@ -86,6 +169,226 @@ impl From<DDPState> for PlayerGameStateResponse {
             team_2_total_points,
             turn: other.default_state.turn,
             top_discard,
+            discard_pile_frozen: other.discard_pile_frozen,
+            seats,
+        }
+    }
+}
+
+/// Points laid down by the team by value, the Queens of Spades
+/// laid down on their own are listed under "QueenOfSpades"
+fn team_points(state: &DDPState, team: usize) -> HashMap<String, Vec<Card>> {
+    let partners = &state.partners[team];
+    let melds = state.card_ids.melds.get(team);
+    let mut points: HashMap<String, Vec<Card>> = partners
+        .points_deck
+        .iter()
+        .map(|(k, v)| {
+            let ids = melds.and_then(|melds| melds.get(k));
+            (format!("{:?}", k), cards_with_ids(v, ids))
+        })
+        .collect();
+    if !partners.queens_of_spades.is_empty() {
+        points.insert(
+            "QueenOfSpades".into(),
+            cards_with_ids(
+                &partners.queens_of_spades,
+                state.card_ids.queens_of_spades.get(team),
+            ),
+        );
+    }
+    points
+}
+
+/// Returns the cards along with the identifiers kept for them, in the same order
+fn cards_with_ids(cards: &[game::models::Card], ids: Option<&Vec<CardId>>) -> Vec<Card> {
+    cards
+        .iter()
+        .enumerate()
+        .map(|(i, card)| Card::with_id(card.clone(), ids.and_then(|ids| ids.get(i))))
+        .collect()
+}
+
+/// Cards of the hand the player could lay down
+#[derive(Clone, Debug, Serialize)]
+pub struct HintResponse {
+    /// One of "complete_set", "wild_set", "extend_meld" or "pickup_discard"
+    kind: String,
+    /// The value the cards are laid down as, as in the team points
+    value: String,
+    card_ids: Vec<String>,
+    points: u16,
+}
+
+impl HintResponse {
+    /// Builds the hint for a suggestion made from a hand
+    ///
+    /// ## Arguments
+    /// `suggestion` - the cards of the hand suggested
+    /// `hand_ids` - the identifiers of the cards of the hand
+    pub fn new(suggestion: Suggestion, hand_ids: &[CardId]) -> HintResponse {
+        let kind = match suggestion.kind {
+            SuggestionKind::CompleteSet => "complete_set",
+            SuggestionKind::WildSet => "wild_set",
+            SuggestionKind::ExtendMeld => "extend_meld",
+            SuggestionKind::PickupDiscard => "pickup_discard",
+        };
+        HintResponse {
+            kind: kind.into(),
+            value: format!("{:?}", suggestion.value),
+            card_ids: suggestion
+                .card_indices
+                .iter()
+                .map(|i| hand_ids[*i].to_string())
+                .collect(),
+            points: suggestion.points,
+        }
+    }
+}
+
+/// Points of every team at the end of a round, sent along with "EndRound"
+#[derive(Clone, Debug, Serialize)]
+pub struct RoundSummaryResponse {
+    teams: Vec<TeamRoundSummaryResponse>,
+    queen_of_spades: QueenOfSpadesRulesResponse,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TeamRoundSummaryResponse {
+    team: usize,
+    melded_points: u16,
+    hand_points: u16,
+    caught_with_queen_of_spades: Vec<usize>,
+    queen_of_spades_penalty: u16,
+    round_points: i16,
+    total_points: i16,
+}
+
+/// The Queen of Spades rules the round was played with
+#[derive(Clone, Debug, Serialize)]
+pub struct QueenOfSpadesRulesResponse {
+    /// Either "as_queen" or "standalone"
+    meld: String,
+    pick_up_from_discard: bool,
+    holding_penalty: u16,
+}
+
+impl RoundSummaryResponse {
+    /// Builds the summary of the last round played, None before the first round ended
+    pub fn for_state(state: &DDPState) -> Option<RoundSummaryResponse> {
+        let summary = state.last_round.as_ref()?;
+        let rules = summary.queen_of_spades;
+        Some(RoundSummaryResponse {
+            teams: summary
+                .teams
+                .iter()
+                .zip(state.partners.iter())
+                .enumerate()
+                .map(|(team, (points, partners))| TeamRoundSummaryResponse {
+                    team,
+                    melded_points: points.melded_points,
+                    hand_points: points.hand_points,
+                    caught_with_queen_of_spades: points.caught_with_queen_of_spades.clone(),
+                    queen_of_spades_penalty: points.queen_of_spades_penalty,
+                    round_points: points.total(),
+                    total_points: partners.overall_points,
+                })
+                .collect(),
+            queen_of_spades: QueenOfSpadesRulesResponse {
+                meld: match rules.meld {
+                    QueenOfSpadesMeld::AsQueen => "as_queen",
+                    QueenOfSpadesMeld::Standalone => "standalone",
+                }
+                .into(),
+                pick_up_from_discard: rules.pick_up_from_discard,
+                holding_penalty: rules.holding_penalty,
+            },
+        })
+    }
+}
+
+impl PlayerGameStateResponse {
+    /// Builds the state of the game as seen by the player in the given seat
+    pub fn for_seat(state: &DDPState, seat: usize) -> PlayerGameStateResponse {
+        let mut response: PlayerGameStateResponse = state.clone().into();
+        response.player_hand = cards_with_ids(
+            &state.default_state.players[seat].hand,
+            state.card_ids.hands.get(seat),
+        );
+        response
+    }
+}
+
+impl From<&GameSession> for SpectatorGameStateResponse {
+    fn from(session: &GameSession) -> SpectatorGameStateResponse {
+        let revealed_hands = session.coach_hands().map(|hands| {
+            hands
+                .iter()
+                .map(|hand| hand.iter().map(|c| Card::from(c.clone())).collect())
+                .collect()
+        });
+        SpectatorGameStateResponse {
+            state: session.state.clone().into(),
+            moves_played: session.moves_played,
+            revealed_hands,
+        }
+    }
+}
+
+impl From<&GameSession> for GameSessionSnapshot {
+    fn from(session: &GameSession) -> GameSessionSnapshot {
+        let mut bot_seats: Vec<usize> = session.bot_seats.iter().cloned().collect();
+        bot_seats.sort();
+        let hands = session
+            .state
+            .default_state
+            .players
+            .iter()
+            .enumerate()
+            .map(|(seat, player)| {
+                cards_with_ids(&player.hand, session.state.card_ids.hands.get(seat))
+            })
+            .collect();
+        GameSessionSnapshot {
+            game: session.inner.clone(),
+            is_active: session.is_active,
+            finished: session.finished,
+            moves_played: session.moves_played,
+            bot_seats,
+            state: session.state.clone().into(),
+            hands,
+        }
+    }
+}
+
+/// Returns the card value named as in `Card`
+pub fn card_value(name: &str) -> Option<CardValue> {
+    match name {
+        "A" => Some(CardValue::Ace),
+        "2" => Some(CardValue::Two),
+        "3" => Some(CardValue::Three),
+        "4" => Some(CardValue::Four),
+        "5" => Some(CardValue::Five),
+        "6" => Some(CardValue::Six),
+        "7" => Some(CardValue::Seven),
+        "8" => Some(CardValue::Eight),
+        "9" => Some(CardValue::Nine),
+        "10" => Some(CardValue::Ten),
+        "J" => Some(CardValue::Jack),
+        "Q" => Some(CardValue::Queen),
+        "K" => Some(CardValue::King),
+        "Joker" => Some(CardValue::Joker),
+        _ => None,
+    }
+}
+
+impl Card {
+    /// Builds the card along with its identifier, when it is known
+    fn with_id(card: game::models::Card, id: Option<&CardId>) -> Card {
+        Card {
+            id: id.map(CardId::to_string),
+            deck: id.map(|id| id.deck),
+            ..Card::from(card)
         }
     }
 }
@@ -111,6 +414,8 @@ impl From<game::models::Card> for Card {
         Card {
             suit: format!("{:?}", other.suit),
             value,
+            id: None,
+            deck: None,
         }
     }
 }
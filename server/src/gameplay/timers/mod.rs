@@ -0,0 +1 @@
+pub mod turn_timer;
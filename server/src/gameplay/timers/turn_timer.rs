@@ -0,0 +1,86 @@
+use crate::gameplay::bot_player;
+use crate::gameplay::gameplay_handlers::send_message_to_players;
+use crate::models::{GameSession, Players, TurnTimerResponse, WebSocketResponse};
+use std::time::Instant;
+
+/// Turn clock of a game session, ticked every second by the game actor
+#[derive(Debug, Default)]
+pub struct TurnTimer {
+    /// Start of the turn the warnings were sent for
+    timed_turn: Option<Instant>,
+    warnings_sent: Vec<u64>,
+}
+
+impl TurnTimer {
+    /// Enforces the turn clock of a game session
+    ///
+    /// ## Purpose
+    /// Warns the table as the current player's time runs down and plays
+    /// the default move for them once it has run out. Nothing happens
+    /// when the table has no turn clock.
+    ///
+    /// ## Arguments
+    /// `session` - the game session being timed
+    /// `players` - persistent collection of players
+    pub async fn tick(&mut self, session: &mut GameSession, players: Players) {
+        let settings = match &session.inner.turn_timer {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
+        if !session.is_active || session.round_over {
+            return;
+        }
+
+        // A new turn started since the last tick
+        if self.timed_turn != Some(session.turn_started_at) {
+            self.timed_turn = Some(session.turn_started_at);
+            self.warnings_sent.clear();
+        }
+
+        let seat = session.state.default_state.turn;
+        let username = session.inner.players.get(seat).cloned().unwrap_or_default();
+        let elapsed = session.turn_started_at.elapsed().as_secs();
+        let seconds_remaining = settings.turn_seconds.saturating_sub(elapsed);
+
+        if seconds_remaining == 0 {
+            println!(
+                "{}'s turn ran out in game {}",
+                username, session.inner.game_id
+            );
+            let message = WebSocketResponse {
+                response_type: "TurnTimerExpired".into(),
+                data: TurnTimerResponse {
+                    username,
+                    seat,
+                    seconds_remaining,
+                },
+            };
+            send_message_to_players(message, session, players.clone()).await;
+            if let Err(e) = bot_player::play_default_turn(session, players.clone()).await {
+                println!("The default move could not be played: {}", e);
+                // Give the player a new turn clock rather than retrying every tick
+                session.turn_started_at = Instant::now();
+            }
+            bot_player::play_bot_turns(session, players).await;
+            return;
+        }
+
+        let warnings_sent = &self.warnings_sent;
+        if let Some(warning) = settings
+            .warning_seconds
+            .iter()
+            .find(|&&warning| seconds_remaining <= warning && !warnings_sent.contains(&warning))
+        {
+            self.warnings_sent.push(*warning);
+            let message = WebSocketResponse {
+                response_type: "TurnTimerWarning".into(),
+                data: TurnTimerResponse {
+                    username,
+                    seat,
+                    seconds_remaining,
+                },
+            };
+            send_message_to_players(message, session, players).await;
+        }
+    }
+}
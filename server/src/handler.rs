@@ -1,20 +1,34 @@
-use std::collections::HashSet;
+use lazy_static::lazy_static;
+use regex::Regex;
 use warp::http::StatusCode;
 use warp::reply::{json, Reply};
-use warp::ws::Message;
 
-use game::gameplay::{DameDePiqueGameBuilder, PlayerMove};
-use game::Game;
-
-use crate::config;
+use crate::config::{Config, SharedConfig};
+use crate::gameplay::game_actor::{GameCommand, GameHandle};
+use crate::health;
+use crate::lobby::{self, Lobby};
+use crate::metrics;
 use crate::models::{
-    GameResponse, GameSession, GameSessionListResponse, GameSessions, Player, PlayerExists,
-    PlayerResponse, Players, RegisterGameRequest, RegisterPlayerRequest, StartGameRequest,
-    StartGameResponse, TooManyPlayers, WebSocketResponse,
+    find_session, find_table, new_invite_code, AlreadySeated, CloseGameRequest, GameResponse,
+    GameSession, GameSessions, HintsDisabled, InvalidCards, InvalidTurnTimer, InvalidUsername,
+    LobbyQuery, LobbyResponse, NotGameHost, Player, PlayerExists, PlayerResponse, Players,
+    RegisterGameRequest, RegisterPlayerRequest, ServerShuttingDown, SpectateGameRequest,
+    StartGameRequest, TableRulesRequest, TooManyPlayers, TooManyRequests, TooManySessions,
+    UndoDisabled,
 };
+use crate::rate_limit::SharedRateLimiter;
+use crate::reaper::{self, CloseReason};
+use crate::shutdown;
 use crate::Result;
+use futures::future::join_all;
+use std::time::Duration;
+use tokio::time::timeout;
+use uuid::Uuid;
 
-type DameDePiqueGame = Game<DameDePiqueGameBuilder, PlayerMove>;
+/// Most tables listed on a page of the lobby
+const MAX_LOBBY_PAGE_SIZE: usize = 100;
+/// Longest a table may take to answer before it is left out of the lobby
+const LOBBY_LISTING_TIMEOUT: Duration = Duration::from_millis(500);
 
 /// Handler for game registration
 ///
@@ -23,105 +37,277 @@ type DameDePiqueGame = Game<DameDePiqueGameBuilder, PlayerMove>;
 ///
 /// ## Arguments
 /// `body` - the game being registered
+/// `config` - the server configuration
+/// `limiter` - the rate limits of the server
+/// `lobby` - the lobby updates channel
 /// `players` - persistent collection of players
 /// `sessions` - persistent collection of game sessions
 pub async fn register_game_handler(
     body: RegisterGameRequest,
+    config: SharedConfig,
+    limiter: SharedRateLimiter,
+    lobby: Lobby,
     players: Players,
     sessions: GameSessions,
 ) -> Result<impl Reply> {
     println!("Registering game: {:?}", body);
-    let game_session = register_game(
-        body.game_identifier.clone(),
-        body.player_username.clone(),
-        players,
-        sessions,
-    )
-    .await;
+    if !limiter.allow_player(&body.player_username) {
+        return Err(warp::reject::custom(TooManyRequests));
+    }
+    let game_session = register_game(body, config, limiter, lobby, players, sessions).await;
     Ok(json(&game_session?))
 }
 
-/// Adds a game to the collection of game sessions
+/// Adds a game to the collection of game sessions, or the player to an existing game
+///
+/// ## Purpose
+/// Tables are created with a server generated identifier and invite code when
+/// neither is given. Private tables can only be joined with their invite code.
 ///
 /// ## Arguments
-/// `game_id` - a unique game identifier
-/// `url` - the game url request when all players are ready to play
-/// `player_username` - the player creating the game session
+/// `body` - the table to join, or the settings of the table to create
+/// `config` - the server configuration
+/// `limiter` - the rate limits the players of a new game count against
+/// `lobby` - the lobby updates channel
 /// `players` - persistent collection of players
 /// `sessions` - persistent collection of the game sessions
 async fn register_game(
-    game_id: String,
-    player_username: String,
+    body: RegisterGameRequest,
+    config: SharedConfig,
+    limiter: SharedRateLimiter,
+    lobby: Lobby,
     players: Players,
     sessions: GameSessions,
 ) -> Result<GameResponse> {
-    let mut sessions = sessions.write().await;
-    if let Some(mut player) = players.write().await.get_mut(&player_username) {
-        player.inner.game_session_id = Some(game_id.clone());
-    } else {
+    let RegisterGameRequest {
+        game_identifier: game_id,
+        invite_code,
+        private,
+        variant,
+        player_username,
+        coach_delay,
+        turn_timer,
+        allow_undo,
+        allow_hints,
+    } = body;
+    if !players.read().await.contains_key(&player_username) {
         // Only registered players can create games
         return Err(warp::reject::not_found());
     }
+    if turn_timer.as_ref().map_or(false, |timer| !timer.is_valid()) {
+        return Err(warp::reject::custom(InvalidTurnTimer));
+    }
 
-    match sessions.get_mut(&game_id) {
-        Some(session) => {
-            if session.inner.players.contains(&player_username) {
-                return Ok(session.inner.clone());
-            }
-            if session.inner.players.len() >= 4 {
-                return Err(warp::reject::custom(TooManyPlayers));
-            }
-            session.inner.players.insert(player_username);
-            players.read().await.iter().for_each(|(_, player)| {
-                if let Some(sender) = &player.sender {
-                    sender
-                        .send(Ok(Message::text(
-                            serde_json::to_string(&WebSocketResponse {
-                                response_type: "GameSession".into(),
-                                data: session.inner.clone(),
-                            })
-                            .unwrap(),
-                        )))
-                        .unwrap();
-                }
-            });
-
-            Ok(session.inner.clone())
+    let mut game_sessions = sessions.write().await;
+    if game_id.is_none() && invite_code.is_none() {
+        if shutdown::is_shutting_down() {
+            return Err(warp::reject::custom(ServerShuttingDown));
+        }
+        let hosted = game_sessions
+            .values()
+            .filter(|game| game.host == player_username)
+            .count();
+        if hosted >= config.max_sessions_per_player {
+            return Err(warp::reject::custom(TooManySessions));
         }
-        None => {
-            let mut players = HashSet::new();
-            players.insert(player_username);
-
-            let game_response = GameResponse {
-                game_id: game_id.clone(),
-                players,
-            };
-            let game_session = GameSession {
-                is_active: false,
-                inner: game_response.clone(),
-                state: DameDePiqueGame::new_game().unwrap(),
-            };
-
-            sessions.insert(game_id.clone(), game_session);
-            Ok(game_response)
+        let game_id = Uuid::new_v4().to_string();
+        let mut game_session = GameSession::new(
+            game_id.clone(),
+            new_invite_code(&game_sessions),
+            private,
+            variant,
+            vec![player_username.clone()],
+            coach_delay,
+            turn_timer,
+        );
+        game_session.inner.undo_allowed = allow_undo;
+        game_session.inner.hints_allowed = allow_hints;
+        let game_response = game_session.inner.clone();
+
+        game_sessions.insert(
+            game_id.clone(),
+            GameHandle::spawn(game_session, players.clone(), config, limiter, lobby),
+        );
+        drop(game_sessions);
+
+        if let Some(player) = players.write().await.get_mut(&player_username) {
+            player.inner.game_session_id = Some(game_id);
         }
+        return Ok(game_response);
+    }
+
+    let game = match find_table(&game_sessions, game_id.as_deref(), invite_code.as_deref()) {
+        Some((_, game)) => game,
+        None => return Err(warp::reject::not_found()),
+    };
+    drop(game_sessions);
+
+    game.request(move |reply| GameCommand::Join {
+        username: player_username,
+        reply,
+    })
+    .await
+    .unwrap_or_else(|| Err(warp::reject::not_found()))
+}
+
+/// Handler for a host closing their game
+///
+/// ## Purpose
+/// Removes the game session and releases everyone at the table,
+/// only the host who created the game may close it
+///
+/// ## Arguments
+/// `body` - the game being closed and the player closing it
+/// `sessions` - persistent collection of game sessions
+pub async fn close_game_handler(
+    body: CloseGameRequest,
+    sessions: GameSessions,
+) -> Result<impl Reply> {
+    println!("Closing game: {:?}", body);
+    let game = match find_session(&sessions, &body.game_id).await {
+        Some(game) => game,
+        None => return Err(warp::reject::not_found()),
+    };
+    if game.host != body.player_username {
+        return Err(warp::reject::custom(NotGameHost));
     }
+    reaper::close_session(&body.game_id, CloseReason::ClosedByHost, &sessions).await;
+    Ok(StatusCode::OK)
+}
+
+/// Handler for the host changing the rules of their table
+///
+/// ## Purpose
+/// Allows or forbids undoing melds and asking for meld suggestions
+///
+/// ## Arguments
+/// `body` - the game, the host's username and the rules being changed
+/// `sessions` - persistent collection of game sessions
+pub async fn table_rules_handler(
+    body: TableRulesRequest,
+    sessions: GameSessions,
+) -> Result<impl Reply> {
+    println!("Setting table rules: {:?}", body);
+    let game = match find_session(&sessions, &body.game_id).await {
+        Some(game) => game,
+        None => return Err(warp::reject::not_found()),
+    };
+    if game.host != body.player_username {
+        return Err(warp::reject::custom(NotGameHost));
+    }
+    let TableRulesRequest {
+        allow_undo,
+        allow_hints,
+        ..
+    } = body;
+    match game
+        .request(|reply| GameCommand::SetRules {
+            allow_undo,
+            allow_hints,
+            reply,
+        })
+        .await
+    {
+        Some(game_response) => Ok(json(&game_response)),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// Handler for spectators joining a game
+///
+/// ## Purpose
+/// Adds a registered player as a spectator of a game session,
+/// spectators do not take a seat at the table
+///
+/// ## Arguments
+/// `body` - the game being watched and the spectator's username
+/// `limiter` - the rate limits of the server
+/// `sessions` - persistent collection of game sessions
+pub async fn spectate_game_handler(
+    body: SpectateGameRequest,
+    limiter: SharedRateLimiter,
+    sessions: GameSessions,
+) -> Result<impl Reply> {
+    println!("Spectating game: {:?}", body);
+    if !limiter.allow_player(&body.username) {
+        return Err(warp::reject::custom(TooManyRequests));
+    }
+    let game = find_table(
+        &*sessions.read().await,
+        Some(&body.game_id),
+        body.invite_code.as_deref(),
+    );
+    let game = match game {
+        Some((_, game)) => game,
+        None => return Err(warp::reject::not_found()),
+    };
+    let username = body.username;
+    let game_response = game
+        .request(move |reply| GameCommand::Spectate { username, reply })
+        .await
+        .unwrap_or_else(|| Err(warp::reject::not_found()))?;
+    Ok(json(&game_response))
 }
 
 /// Handler for the list of games in lobby
 ///
 /// ## Purpose
-/// Returns the list of active game sessions
+/// Returns a page of the public game sessions matching the filters,
+/// ordered by game identifier so pages stay stable
 ///
 /// ## Arguments
+/// `query` - the filters and page requested
 /// `sessions` - persistent collection of game sessions
-pub async fn get_lobby(sessions: GameSessions) -> Result<impl Reply> {
-    let sessions = sessions.read().await;
-    Ok(json(&GameSessionListResponse {
-        games: sessions.values().cloned().map(|v| v.inner).collect(),
+pub async fn get_lobby(query: LobbyQuery, sessions: GameSessions) -> Result<impl Reply> {
+    let sessions: Vec<GameHandle> = sessions.read().await.values().cloned().collect();
+    let listings = join_all(sessions.iter().filter(|game| !game.private).map(|game| {
+        timeout(
+            LOBBY_LISTING_TIMEOUT,
+            game.request(|reply| GameCommand::Listing { reply }),
+        )
+    }))
+    .await;
+    // Games closed in the meantime, or too busy to answer, are left out
+    let mut games: Vec<_> = listings
+        .into_iter()
+        .filter_map(|listing| listing.ok().flatten())
+        .collect();
+    games.retain(|entry| {
+        query.status.map_or(true, |status| entry.status == status)
+            && query
+                .variant
+                .map_or(true, |variant| entry.variant == variant)
+    });
+    games.sort_by(|a, b| a.game_id.cmp(&b.game_id));
+
+    let page = query.page.max(1);
+    let per_page = query.per_page.max(1).min(MAX_LOBBY_PAGE_SIZE);
+    let total = games.len();
+    let games = games
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .collect();
+    Ok(json(&LobbyResponse {
+        games,
+        page,
+        per_page,
+        total,
     }))
 }
 
+/// Handler for a websocket subscribing to the lobby
+///
+/// ## Purpose
+/// Streams changes to the public tables for the players browsing the lobby
+///
+/// ## Arguments
+/// `ws` - the websocket connection
+/// `lobby` - the lobby updates channel
+pub async fn lobby_ws_handler(ws: warp::ws::Ws, lobby: Lobby) -> Result<impl Reply> {
+    Ok(ws.on_upgrade(move |socket| lobby::lobby_connection(socket, lobby)))
+}
+
 /// Handler for players registering to the game
 ///
 /// ## Purpose
@@ -129,13 +315,15 @@ pub async fn get_lobby(sessions: GameSessions) -> Result<impl Reply> {
 ///
 /// ## Arguments
 /// `body` - the player username being registered
+/// `config` - the server configuration
 /// `players` - persistent collection of players
 pub async fn register_player_handler(
     body: RegisterPlayerRequest,
+    config: SharedConfig,
     players: Players,
 ) -> Result<impl Reply> {
     println!("Registering player: {:?}", body);
-    let player_response = register_player(body.username, players).await?;
+    let player_response = register_player(body.username, &config, players).await?;
     Ok(json(&player_response))
 }
 
@@ -143,81 +331,73 @@ pub async fn register_player_handler(
 ///
 /// ## Arguments
 /// `username` - the player username being registered
+/// `config` - the server configuration
 /// `players` - persistent collection of players
-async fn register_player(username: String, players: Players) -> Result<PlayerResponse> {
+async fn register_player(
+    username: String,
+    config: &Config,
+    players: Players,
+) -> Result<PlayerResponse> {
+    if !is_valid_username(&username) {
+        return Err(warp::reject::custom(InvalidUsername));
+    }
     let mut players = players.write().await;
     if players.contains_key(&username) {
         return Err(warp::reject::custom(PlayerExists));
     }
 
-    let config = config::load_config();
-
     let player_response = PlayerResponse {
         username: username.clone(),
         game_session_id: None,
-        websocket_url: format!(
-            "ws://{}:{}/ws/{}",
-            config.websocket_host, config.port, username
-        ),
+        websocket_url: config.websocket_url(&username),
     };
     players.insert(
         username.clone(),
         Player {
             inner: player_response.clone(),
             sender: None,
+            connection: 0,
         },
     );
 
     Ok(player_response)
 }
 
+/// Returns whether the username may be registered
+///
+/// ## Purpose
+/// Usernames are 3 to 20 letters, digits, dashes or underscores and
+/// can't be mistaken for the server or the default names of the seats
+fn is_valid_username(username: &str) -> bool {
+    lazy_static! {
+        static ref USERNAME: Regex = Regex::new(r"^[A-Za-z0-9_-]{3,20}$").unwrap();
+        static ref RESERVED: Regex =
+            Regex::new(r"(?i)^(admin|administrator|server|system|bot|player|player[_-]?\d+)$")
+                .unwrap();
+    }
+    USERNAME.is_match(username) && !RESERVED.is_match(username)
+}
+
 pub async fn start_game_handler(
     body: StartGameRequest,
-    players: Players,
     sessions: GameSessions,
 ) -> Result<impl Reply> {
     println!("Starting Game {}", body.game_id);
 
-    let mut sessions = sessions.write().await;
-    let mut session = sessions.get_mut(&body.game_id).unwrap();
-
-    let team_1_points = session.state.partners[0].overall_points;
-    let team_2_points = session.state.partners[1].overall_points;
-
-    session.state = DameDePiqueGame::new_game().unwrap();
-
-    session.state.partners[0].overall_points = team_1_points;
-    session.state.partners[1].overall_points = team_2_points;
-
-    let players = players.read().await;
-    let response_type = if session.is_active {
-        "GameState"
-    } else {
-        "StartGameResponse"
+    let game = match find_session(&sessions, &body.game_id).await {
+        Some(game) => game,
+        None => return Err(warp::reject::not_found()),
     };
-    session.is_active = true;
-    session.inner.players.iter().for_each(|player_id: &String| {
-        if let Some(player) = players.get(player_id) {
-            if let Some(sender) = &player.sender {
-                sender
-                    .send(Ok(Message::text(
-                        serde_json::to_string(&WebSocketResponse {
-                            response_type: response_type.into(),
-                            data: StartGameResponse {},
-                        })
-                        .unwrap(),
-                    )))
-                    .unwrap();
-            }
-        }
-    });
-
-    Ok(StatusCode::OK)
+    match game.request(|reply| GameCommand::Start { reply }).await {
+        Some(()) => Ok(StatusCode::OK),
+        None => Err(warp::reject::not_found()),
+    }
 }
 
 pub async fn ws_handler(
     ws: warp::ws::Ws,
     player_id: String,
+    config: SharedConfig,
     players: Players,
     games: GameSessions,
 ) -> Result<impl Reply> {
@@ -225,13 +405,136 @@ pub async fn ws_handler(
     let player = players.read().await.get(&player_id).cloned();
     match player {
         Some(c) => Ok(ws.on_upgrade(move |socket| {
-            crate::ws::client_connection(socket, player_id, players, games, c)
+            crate::ws::client_connection(socket, player_id, config, players, games, c)
         })),
         None => Err(warp::reject::not_found()),
     }
 }
 
-pub async fn health_handler() -> Result<impl Reply> {
+/// Handler for the operational metrics of the server
+///
+/// ## Purpose
+/// Reports the sessions, websockets, moves and request latencies
+/// in the Prometheus text exposition format
+///
+/// ## Arguments
+/// `players` - persistent collection of players
+/// `sessions` - persistent collection of game sessions
+pub async fn metrics_handler(players: Players, sessions: GameSessions) -> Result<impl Reply> {
+    let sessions = sessions.read().await.len();
+    let (active_websockets, idle_websockets) = players
+        .read()
+        .await
+        .values()
+        .filter(|player| player.sender.is_some())
+        .fold((0, 0), |(active, idle), player| {
+            match player.inner.game_session_id {
+                Some(_) => (active + 1, idle),
+                None => (active, idle + 1),
+            }
+        });
+
+    let body = metrics::render(&metrics::Gauges {
+        sessions,
+        active_websockets,
+        idle_websockets,
+    });
+    Ok(warp::reply::with_header(
+        body,
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
+
+/// Answers the rejections of the server with their status code,
+/// every other rejection is left to warp
+pub async fn handle_rejection(
+    rejection: warp::Rejection,
+) -> std::result::Result<impl Reply, warp::Rejection> {
+    let (status, message) = if rejection.find::<TooManyRequests>().is_some() {
+        (StatusCode::TOO_MANY_REQUESTS, "Too many requests")
+    } else if rejection.find::<TooManySessions>().is_some() {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many game sessions hosted by the player",
+        )
+    } else if rejection.find::<ServerShuttingDown>().is_some() {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The server is shutting down",
+        )
+    } else if rejection.find::<NotGameHost>().is_some() {
+        (
+            StatusCode::FORBIDDEN,
+            "Only the host of the table can do this",
+        )
+    } else if rejection.find::<AlreadySeated>().is_some() {
+        (
+            StatusCode::CONFLICT,
+            "Players seated at the table can't spectate it",
+        )
+    } else if rejection.find::<TooManyPlayers>().is_some() {
+        (StatusCode::CONFLICT, "Every seat of the table is taken")
+    } else if rejection.find::<PlayerExists>().is_some() {
+        (StatusCode::CONFLICT, "The username is already taken")
+    } else if rejection.find::<UndoDisabled>().is_some() {
+        (
+            StatusCode::FORBIDDEN,
+            "The host disabled undo at this table",
+        )
+    } else if rejection.find::<HintsDisabled>().is_some() {
+        (
+            StatusCode::FORBIDDEN,
+            "The host disabled hints at this table",
+        )
+    } else if rejection.find::<InvalidCards>().is_some() {
+        (
+            StatusCode::BAD_REQUEST,
+            "Cards are unknown or given more than once",
+        )
+    } else if rejection.find::<InvalidTurnTimer>().is_some() {
+        (
+            StatusCode::BAD_REQUEST,
+            "Turns last at least a second and are warned of before they run out",
+        )
+    } else if rejection.find::<InvalidUsername>().is_some() {
+        (
+            StatusCode::BAD_REQUEST,
+            "Usernames are 3 to 20 letters, digits, dashes or underscores",
+        )
+    } else {
+        return Err(rejection);
+    };
+    Ok(warp::reply::with_status(message, status))
+}
+
+/// Handler for the readiness of the server
+///
+/// ## Purpose
+/// Reports the diagnostics of the server, answering 503 when
+/// it should not be sent traffic
+///
+/// ## Arguments
+/// `config` - the server configuration
+/// `players` - persistent collection of players
+/// `sessions` - persistent collection of game sessions
+pub async fn health_handler(
+    config: SharedConfig,
+    players: Players,
+    sessions: GameSessions,
+) -> Result<impl Reply> {
+    let health = health::check(&config, &players, &sessions).await;
+    let status = if health.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok(warp::reply::with_status(json(&health), status))
+}
+
+/// Handler for the liveness of the server, answers as long
+/// as the server is able to handle requests at all
+pub async fn liveness_handler() -> Result<impl Reply> {
     Ok(StatusCode::OK)
 }
 
@@ -244,10 +547,82 @@ pub async fn unregister_player_handler(
     let mut removed_player = None;
     if let Some(player) = player {
         if let Some(game_session_id) = player.inner.game_session_id {
-            if let Some(session) = sessions.write().await.get_mut(&game_session_id) {
-                removed_player = Some(session.inner.players.remove(&username));
+            if let Some(game) = find_session(&sessions, &game_session_id).await {
+                removed_player = game
+                    .request(|reply| GameCommand::Leave { username, reply })
+                    .await;
             }
         }
     }
     Ok(json(&removed_player))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RuleVariant;
+    use crate::rate_limit::RateLimiter;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn usernames_must_match_the_pattern() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let config = Config::default();
+        for username in &[
+            "ab",
+            "north south",
+            "north!",
+            "player_2",
+            "a_very_long_username_2",
+        ] {
+            let rejection = register_player(username.to_string(), &config, players.clone())
+                .await
+                .unwrap_err();
+            assert!(rejection.find::<InvalidUsername>().is_some());
+        }
+        assert!(players.read().await.is_empty());
+
+        let player = register_player("north_2".into(), &config, players.clone())
+            .await
+            .unwrap();
+        assert_eq!(player.username, "north_2");
+        assert!(players.read().await.contains_key("north_2"));
+    }
+
+    #[tokio::test]
+    async fn only_the_host_closes_the_game() {
+        let players: Players = Arc::new(RwLock::new(HashMap::new()));
+        let sessions: GameSessions = Arc::new(RwLock::new(HashMap::new()));
+        let session = GameSession::new(
+            "table".into(),
+            "TABLE".into(),
+            false,
+            RuleVariant::Classic,
+            vec!["north".into(), "east".into()],
+            None,
+            None,
+        );
+        let config = Config::default();
+        let limiter = Arc::new(RateLimiter::from(&config));
+        let game = GameHandle::spawn(session, players, Arc::new(config), limiter, Lobby::new());
+        sessions.write().await.insert("table".into(), game);
+
+        let close = |player_username: &str| CloseGameRequest {
+            game_id: "table".into(),
+            player_username: player_username.into(),
+        };
+        let rejection = close_game_handler(close("east"), sessions.clone())
+            .await
+            .err()
+            .unwrap();
+        assert!(rejection.find::<NotGameHost>().is_some());
+        assert!(sessions.read().await.contains_key("table"));
+
+        assert!(close_game_handler(close("north"), sessions.clone())
+            .await
+            .is_ok());
+        assert!(sessions.read().await.is_empty());
+    }
+}
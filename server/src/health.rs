@@ -0,0 +1,118 @@
+use crate::config::Config;
+use crate::gameplay::game_actor::{GameCommand, GameHandle};
+use crate::models::{GameSessions, Players};
+use crate::shutdown;
+use futures::future::join_all;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+/// Longest a lock or a game session may take to answer before the server is not ready
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// When the server started
+    pub static ref STARTED_AT: Instant = Instant::now();
+}
+
+/// Diagnostics of the server, used to decide whether it can take traffic
+#[derive(Serialize, Debug)]
+pub struct HealthResponse {
+    pub ready: bool,
+    pub uptime_seconds: u64,
+    pub version: &'static str,
+    pub shutting_down: bool,
+    pub sessions: usize,
+    pub players: usize,
+    pub connected_players: usize,
+    /// Game sessions that didn't answer in time
+    pub unresponsive_sessions: usize,
+    /// Time spent waiting on the collection of game sessions
+    pub sessions_lock_wait_ms: Option<u128>,
+    /// Time spent waiting on the collection of players
+    pub players_lock_wait_ms: Option<u128>,
+    /// Whether game sessions can be saved to the snapshot directory
+    pub storage_writable: bool,
+}
+
+/// Checks every part of the server a request depends on
+///
+/// ## Arguments
+/// `config` - the server configuration
+/// `players` - persistent collection of players
+/// `sessions` - persistent collection of game sessions
+pub async fn check(config: &Config, players: &Players, sessions: &GameSessions) -> HealthResponse {
+    let waiting = Instant::now();
+    let games: Option<Vec<GameHandle>> = timeout(RESPONSE_TIMEOUT, sessions.read())
+        .await
+        .ok()
+        .map(|sessions| sessions.values().cloned().collect());
+    let sessions_lock_wait_ms = games.as_ref().map(|_| waiting.elapsed().as_millis());
+    let games = games.unwrap_or_default();
+
+    let waiting = Instant::now();
+    let player_counts = timeout(RESPONSE_TIMEOUT, players.read())
+        .await
+        .ok()
+        .map(|players| {
+            let connected = players.values().filter(|p| p.sender.is_some()).count();
+            (players.len(), connected)
+        });
+    let players_lock_wait_ms = player_counts.map(|_| waiting.elapsed().as_millis());
+    let (player_count, connected_players) = player_counts.unwrap_or_default();
+
+    let answers = join_all(games.iter().map(|game| {
+        timeout(
+            RESPONSE_TIMEOUT,
+            game.request(|reply| GameCommand::Info { reply }),
+        )
+    }))
+    .await;
+    let unresponsive_sessions = answers
+        .iter()
+        .filter(|answer| !matches!(answer, Ok(Some(_))))
+        .count();
+
+    let storage_writable = is_writable(&config.snapshot_dir);
+    let shutting_down = shutdown::is_shutting_down();
+    HealthResponse {
+        ready: !shutting_down
+            && sessions_lock_wait_ms.is_some()
+            && players_lock_wait_ms.is_some()
+            && unresponsive_sessions == 0
+            && storage_writable,
+        uptime_seconds: STARTED_AT.elapsed().as_secs(),
+        version: env!("CARGO_PKG_VERSION"),
+        shutting_down,
+        sessions: games.len(),
+        players: player_count,
+        connected_players,
+        unresponsive_sessions,
+        sessions_lock_wait_ms,
+        players_lock_wait_ms,
+        storage_writable,
+    }
+}
+
+/// Returns whether files can be created in the directory, without writing to the disk
+///
+/// ## Purpose
+/// The snapshots create the directory when it is missing, its closest
+/// existing parent then has to be writable instead
+fn is_writable(directory: &str) -> bool {
+    let mut path = Path::new(directory);
+    loop {
+        match fs::metadata(path) {
+            Ok(metadata) => return metadata.is_dir() && !metadata.permissions().readonly(),
+            Err(_) => match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => path = parent,
+                // A relative directory is created in the working directory
+                Some(_) if path != Path::new(".") => path = Path::new("."),
+                _ => return false,
+            },
+        }
+    }
+}
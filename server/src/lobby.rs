@@ -0,0 +1,87 @@
+use crate::models::{LobbyEntry, WebSocketResponse};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, RecvError};
+use warp::ws::{Message, WebSocket};
+
+/// Lobby updates kept for subscribers slower than the rest
+const LOBBY_BACKLOG: usize = 64;
+
+/// A change to the list of public tables
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LobbyEvent {
+    /// A table was opened or its seats or status changed
+    Updated { table: LobbyEntry },
+    /// A table was closed
+    Removed { game_id: String },
+}
+
+/// Channel publishing lobby updates to the players browsing the lobby
+///
+/// ## Purpose
+/// Players at a table don't need to hear about the others, so lobby updates
+/// only go to the websockets subscribed to the lobby.
+#[derive(Clone)]
+pub struct Lobby {
+    sender: broadcast::Sender<LobbyEvent>,
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lobby {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(LOBBY_BACKLOG);
+        Self { sender }
+    }
+
+    /// Sends the update to every subscriber
+    pub fn publish(&self, event: LobbyEvent) {
+        // Nobody may be browsing the lobby
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LobbyEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Streams lobby updates to a websocket until it closes
+///
+/// ## Arguments
+/// `ws` - the websocket subscribed to the lobby
+/// `lobby` - the lobby updates channel
+pub async fn lobby_connection(ws: WebSocket, lobby: Lobby) {
+    let (mut ws_sender, mut ws_rcv) = ws.split();
+    let mut events = lobby.subscribe();
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let message = match event {
+                    Ok(event) => serde_json::to_string(&WebSocketResponse {
+                        response_type: "LobbyUpdate".into(),
+                        data: event,
+                    }),
+                    // Updates were missed, the subscriber has to fetch the lobby again
+                    Err(RecvError::Lagged(_)) => serde_json::to_string(&WebSocketResponse {
+                        response_type: "LobbyStale".into(),
+                        data: {},
+                    }),
+                    Err(RecvError::Closed) => break,
+                }
+                .unwrap();
+                if ws_sender.send(Message::text(message)).await.is_err() {
+                    break;
+                }
+            }
+            message = ws_rcv.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => break,
+            },
+        }
+    }
+}
@@ -1,29 +1,65 @@
 use std::collections::HashMap;
 use std::convert::Infallible;
-use std::net::SocketAddr;
+use std::process;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use warp::{http::Method, Filter, Rejection};
+use warp::{
+    http::{Method, StatusCode},
+    Filter, Rejection,
+};
 mod config;
 mod gameplay;
 mod handler;
+mod health;
+mod lobby;
+mod metrics;
 mod models;
+mod rate_limit;
+mod reaper;
+mod shutdown;
 mod ws;
 
-use models::{GameSessions, Players};
+use config::SharedConfig;
+use gameplay::gameplay_models::{GameQuery, SeatQuery};
+use lobby::Lobby;
+use models::{GameSessions, LobbyQuery, Players};
+use rate_limit::{RateLimiter, SharedRateLimiter};
 type Result<T> = std::result::Result<T, Rejection>;
 
 #[tokio::main]
 async fn main() {
+    lazy_static::initialize(&health::STARTED_AT);
+    let config: SharedConfig = match config::load_config() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("Could not load the server configuration: {}", e);
+            process::exit(1);
+        }
+    };
+    let limiter: SharedRateLimiter = Arc::new(RateLimiter::from(&*config));
     let sessions: GameSessions = Arc::new(RwLock::new(HashMap::new()));
     let players: Players = Arc::new(RwLock::new(HashMap::new()));
+    let lobby = Lobby::new();
 
-    let health_route = warp::path!("health").and_then(handler::health_handler);
+    let health_route = warp::path!("health")
+        .and(with_config(config.clone()))
+        .and(with_players(players.clone()))
+        .and(with_game_sessions(sessions.clone()))
+        .and_then(handler::health_handler);
+
+    let liveness_route = warp::path!("live").and_then(handler::liveness_handler);
+
+    let metrics_route = warp::path!("metrics")
+        .and(warp::get())
+        .and(with_players(players.clone()))
+        .and(with_game_sessions(sessions.clone()))
+        .and_then(handler::metrics_handler);
 
     let player_register = warp::path("player-register");
     let player_register_route = player_register
         .and(warp::post())
         .and(warp::body::json())
+        .and(with_config(config.clone()))
         .and(with_players(players.clone()))
         .and_then(handler::register_player_handler)
         .or(player_register
@@ -35,91 +71,185 @@ async fn main() {
 
     let start_game_route = warp::path!("game-start")
         .and(warp::body::json())
-        .and(with_players(players.clone()))
         .and(with_game_sessions(sessions.clone()))
         .and_then(handler::start_game_handler);
 
     let lobby_route = warp::path!("lobby")
+        .and(warp::query::<LobbyQuery>())
         .and(with_game_sessions(sessions.clone()))
         .and_then(handler::get_lobby);
 
+    let lobby_ws_route = warp::path!("lobby-ws")
+        .and(warp::ws())
+        .and(with_lobby(lobby.clone()))
+        .and_then(handler::lobby_ws_handler);
+
     let game_register = warp::path("game-register");
     let game_register_route = game_register
         .and(warp::post())
         .and(warp::body::json())
+        .and(with_config(config.clone()))
+        .and(with_rate_limiter(limiter.clone()))
+        .and(with_lobby(lobby.clone()))
         .and(with_players(players.clone()))
         .and(with_game_sessions(sessions.clone()))
-        .and_then(handler::register_game_handler);
+        .and_then(handler::register_game_handler)
+        .or(game_register
+            .and(warp::delete())
+            .and(warp::body::json())
+            .and(with_game_sessions(sessions.clone()))
+            .and_then(handler::close_game_handler));
+
+    let game_spectate_route = warp::path("game-spectate")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_rate_limiter(limiter.clone()))
+        .and(with_game_sessions(sessions.clone()))
+        .and_then(handler::spectate_game_handler);
 
     let ws_route = warp::path("ws")
         .and(warp::ws())
         .and(warp::path::param())
+        .and(with_config(config.clone()))
         .and(with_players(players.clone()))
         .and(with_game_sessions(sessions.clone()))
         .and_then(handler::ws_handler);
 
     let gameplay_route = warp::path("game-state")
-        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::query::<SeatQuery>())
         .and(with_game_sessions(sessions.clone()))
         .and_then(gameplay::gameplay_handlers::get_game_state_handler);
 
+    let spectator_state_route = warp::path("spectator-state")
+        .and(warp::query::<GameQuery>())
+        .and(with_game_sessions(sessions.clone()))
+        .and_then(gameplay::gameplay_handlers::get_spectator_state_handler);
+
     let game_action_draw_route = warp::path("draw-card")
-        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::query::<GameQuery>())
         .and(with_game_sessions(sessions.clone()))
         .and_then(gameplay::gameplay_handlers::draw_card_handler);
 
     let game_action_discard_route = warp::path("discard-card")
         .and(warp::post())
         .and(warp::body::json())
-        .and(with_players(players.clone()))
         .and(with_game_sessions(sessions.clone()))
         .and_then(gameplay::gameplay_handlers::discard_handler);
 
     let game_action_open_route = warp::path("player-open")
         .and(warp::post())
         .and(warp::body::json())
-        .and(with_players(players.clone()))
         .and(with_game_sessions(sessions.clone()))
         .and_then(gameplay::gameplay_handlers::player_open_handler);
 
     let game_action_points_route = warp::path("player-add-points")
         .and(warp::post())
         .and(warp::body::json())
-        .and(with_players(players.clone()))
         .and(with_game_sessions(sessions.clone()))
         .and_then(gameplay::gameplay_handlers::player_add_points_handler);
 
     let game_player_pickup_discard_route = warp::path("player-pickup-discard")
         .and(warp::post())
         .and(warp::body::json())
-        .and(with_players(players.clone()))
         .and(with_game_sessions(sessions.clone()))
         .and_then(gameplay::gameplay_handlers::player_pickup_discard_handler);
 
+    let game_action_undo_route = warp::path("player-undo")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_game_sessions(sessions.clone()))
+        .and_then(gameplay::gameplay_handlers::player_undo_handler);
+
+    let game_hints_route = warp::path("hints")
+        .and(warp::query::<SeatQuery>())
+        .and(with_game_sessions(sessions.clone()))
+        .and_then(gameplay::gameplay_handlers::get_hints_handler);
+
+    let game_rules_route = warp::path("game-rules")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_game_sessions(sessions.clone()))
+        .and_then(handler::table_rules_handler);
+
     let cors = warp::cors()
         .allow_any_origin()
         .allow_header("content-type")
         .allow_methods(&[Method::GET, Method::POST, Method::DELETE]);
-    let routes = health_route
-        .or(player_register_route)
+    // Operational routes are left out of the rate limits
+    let public_routes = player_register_route
         .or(game_register_route)
+        .or(game_spectate_route)
         .or(lobby_route)
+        .or(lobby_ws_route)
         .or(ws_route)
         .or(start_game_route)
         .or(gameplay_route)
+        .or(spectator_state_route)
         .or(game_action_draw_route)
         .or(game_action_discard_route)
         .or(game_action_open_route)
         .or(game_action_points_route)
         .or(game_player_pickup_discard_route)
-        .with(cors);
+        .or(game_action_undo_route)
+        .or(game_hints_route)
+        .or(game_rules_route);
+    let routes = health_route
+        .or(liveness_route)
+        .or(metrics_route)
+        .or(rate_limit::with_ip_limit(limiter.clone()).and(public_routes))
+        .recover(handler::handle_rejection)
+        .with(cors)
+        .with(warp::log::custom(|info| {
+            // Routes are labelled by their first segment to keep usernames out of the labels
+            let route = match info.status() {
+                StatusCode::NOT_FOUND => "unmatched",
+                _ => info
+                    .path()
+                    .trim_start_matches('/')
+                    .split('/')
+                    .next()
+                    .unwrap_or_default(),
+            };
+            metrics::observe_request(route, info.elapsed());
+        }));
 
-    let config = config::load_config();
+    tokio::task::spawn(reaper::run_reaper(config.clone(), sessions.clone()));
+
+    let shutdown = shutdown::shutdown_on_signal(config.clone(), players.clone(), sessions.clone());
+    match &config.tls {
+        Some(tls) => {
+            println!("Listening on {} over TLS", config.address);
+            let (_, server) = warp::serve(routes)
+                .tls()
+                .cert_path(&tls.cert_path)
+                .key_path(&tls.key_path)
+                .bind_with_graceful_shutdown(config.address, shutdown);
+            server.await;
+        }
+        None => {
+            println!("Listening on {}", config.address);
+            let (_, server) =
+                warp::serve(routes).bind_with_graceful_shutdown(config.address, shutdown);
+            server.await;
+        }
+    }
+    println!("Server stopped");
+}
+
+fn with_config(
+    config: SharedConfig,
+) -> impl Filter<Extract = (SharedConfig,), Error = Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
+
+fn with_rate_limiter(
+    limiter: SharedRateLimiter,
+) -> impl Filter<Extract = (SharedRateLimiter,), Error = Infallible> + Clone {
+    warp::any().map(move || limiter.clone())
+}
 
-    println!("Listening on {}:{}", config.host, config.port);
-    warp::serve(routes)
-        .run(SocketAddr::new(config.host, config.port))
-        .await;
+fn with_lobby(lobby: Lobby) -> impl Filter<Extract = (Lobby,), Error = Infallible> + Clone {
+    warp::any().map(move || lobby.clone())
 }
 
 fn with_game_sessions(
@@ -0,0 +1,305 @@
+use game::error::DameDePiqueError;
+use game::gameplay::PlayerMove;
+use game::rules::GameStatus;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Game sessions removed after going without activity for too long
+pub static SESSIONS_REAPED_IDLE: AtomicUsize = AtomicUsize::new(0);
+/// Game sessions removed after the game was won
+pub static SESSIONS_REAPED_FINISHED: AtomicUsize = AtomicUsize::new(0);
+/// Game sessions removed after every player left
+pub static SESSIONS_REAPED_ABANDONED: AtomicUsize = AtomicUsize::new(0);
+/// Game sessions closed by their host
+pub static SESSIONS_CLOSED: AtomicUsize = AtomicUsize::new(0);
+/// Game sessions closed when the server shut down
+pub static SESSIONS_SHUT_DOWN: AtomicUsize = AtomicUsize::new(0);
+
+/// Rounds that ended, including the rounds ending a game
+pub static ROUNDS_COMPLETED: AtomicUsize = AtomicUsize::new(0);
+/// Games won by a team
+pub static GAMES_COMPLETED: AtomicUsize = AtomicUsize::new(0);
+
+const MOVE_KINDS: [&str; 6] = [
+    "draw",
+    "open",
+    "add_points",
+    "take_discard_pile",
+    "discard",
+    "undo",
+];
+static MOVES_PLAYED: [AtomicUsize; 6] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+const ERROR_KINDS: [&str; 7] = [
+    "incorrect_card_number_request",
+    "deck_empty",
+    "invalid_opening_hand",
+    "invalid_discard_opening_hand",
+    "player_cant_add_points",
+    "invalid_points",
+    "nothing_to_undo",
+];
+static MOVES_REJECTED: [AtomicUsize; 7] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Upper bounds of the request latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+lazy_static! {
+    /// Request latencies by route
+    static ref LATENCIES: Mutex<HashMap<String, Histogram>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; 11],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Returns the total amount of game sessions removed by the reaper
+pub fn sessions_reaped() -> usize {
+    SESSIONS_REAPED_IDLE.load(Ordering::Relaxed)
+        + SESSIONS_REAPED_FINISHED.load(Ordering::Relaxed)
+        + SESSIONS_REAPED_ABANDONED.load(Ordering::Relaxed)
+}
+
+/// Returns the index of the move's label in `MOVE_KINDS`
+pub fn move_kind(player_move: &PlayerMove) -> usize {
+    match player_move {
+        PlayerMove::Draw => 0,
+        PlayerMove::Open(_) => 1,
+        PlayerMove::AddPoints(_) => 2,
+        PlayerMove::TakeDiscardPile(_) => 3,
+        PlayerMove::Discard(_) => 4,
+        PlayerMove::Undo => 5,
+    }
+}
+
+fn error_index(error: &DameDePiqueError) -> usize {
+    match error {
+        DameDePiqueError::IncorrectCardNumberRequest => 0,
+        DameDePiqueError::DeckEmpty => 1,
+        DameDePiqueError::InvalidOpeningHand(_) => 2,
+        DameDePiqueError::InvalidDiscardOpeningHand(_) => 3,
+        DameDePiqueError::PlayerCantAddPoints(_) => 4,
+        DameDePiqueError::InvalidPoints => 5,
+        DameDePiqueError::NothingToUndo(_) => 6,
+    }
+}
+
+/// Counts a move played by a player or a bot
+///
+/// ## Arguments
+/// `kind` - the kind of move, see `move_kind`
+/// `result` - the outcome of the move
+pub fn record_move(kind: usize, result: &Result<GameStatus, DameDePiqueError>) {
+    match result {
+        Ok(status) => {
+            MOVES_PLAYED[kind].fetch_add(1, Ordering::Relaxed);
+            match status {
+                GameStatus::RoundOver => {
+                    ROUNDS_COMPLETED.fetch_add(1, Ordering::Relaxed);
+                }
+                GameStatus::GameOver => {
+                    ROUNDS_COMPLETED.fetch_add(1, Ordering::Relaxed);
+                    GAMES_COMPLETED.fetch_add(1, Ordering::Relaxed);
+                }
+                _ => {}
+            }
+        }
+        Err(e) => {
+            MOVES_REJECTED[error_index(e)].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Records how long the server took to answer a request
+///
+/// ## Arguments
+/// `route` - the first segment of the request path
+/// `elapsed` - the time spent handling the request
+pub fn observe_request(route: &str, elapsed: Duration) {
+    let mut latencies = LATENCIES.lock().unwrap();
+    latencies
+        .entry(route.to_string())
+        .or_default()
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Live figures read from the server state when the metrics are scraped
+pub struct Gauges {
+    pub sessions: usize,
+    pub active_websockets: usize,
+    pub idle_websockets: usize,
+}
+
+/// Renders every metric in the Prometheus text exposition format
+pub fn render(gauges: &Gauges) -> String {
+    let mut out = String::new();
+    gauge(
+        &mut out,
+        "ddp_sessions_active",
+        "Game sessions currently registered",
+        gauges.sessions,
+    );
+    let _ = writeln!(
+        out,
+        "# HELP ddp_websockets Connected websockets, idle ones are not at a table"
+    );
+    let _ = writeln!(out, "# TYPE ddp_websockets gauge");
+    let _ = writeln!(
+        out,
+        "ddp_websockets{{state=\"active\"}} {}",
+        gauges.active_websockets
+    );
+    let _ = writeln!(
+        out,
+        "ddp_websockets{{state=\"idle\"}} {}",
+        gauges.idle_websockets
+    );
+
+    labelled_counter(
+        &mut out,
+        "ddp_moves_total",
+        "Moves played by kind",
+        "kind",
+        &MOVE_KINDS,
+        &MOVES_PLAYED.iter().collect::<Vec<_>>(),
+    );
+    labelled_counter(
+        &mut out,
+        "ddp_moves_rejected_total",
+        "Moves rejected by the rules, by error",
+        "error",
+        &ERROR_KINDS,
+        &MOVES_REJECTED.iter().collect::<Vec<_>>(),
+    );
+    counter(
+        &mut out,
+        "ddp_rounds_completed_total",
+        "Rounds that ended",
+        &ROUNDS_COMPLETED,
+    );
+    counter(
+        &mut out,
+        "ddp_games_completed_total",
+        "Games won by a team",
+        &GAMES_COMPLETED,
+    );
+    labelled_counter(
+        &mut out,
+        "ddp_sessions_closed_total",
+        "Game sessions removed, by reason",
+        "reason",
+        &["idle", "finished", "abandoned", "host", "shutdown"],
+        &[
+            &SESSIONS_REAPED_IDLE,
+            &SESSIONS_REAPED_FINISHED,
+            &SESSIONS_REAPED_ABANDONED,
+            &SESSIONS_CLOSED,
+            &SESSIONS_SHUT_DOWN,
+        ],
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP ddp_request_duration_seconds Time spent handling requests, by route"
+    );
+    let _ = writeln!(out, "# TYPE ddp_request_duration_seconds histogram");
+    let latencies = LATENCIES.lock().unwrap();
+    let mut routes: Vec<&String> = latencies.keys().collect();
+    routes.sort();
+    for route in routes {
+        let histogram = &latencies[route];
+        for (count, bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+            let _ = writeln!(
+                out,
+                "ddp_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                route, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "ddp_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+            route, histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "ddp_request_duration_seconds_sum{{route=\"{}\"}} {}",
+            route, histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "ddp_request_duration_seconds_count{{route=\"{}\"}} {}",
+            route, histogram.count
+        );
+    }
+    out
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: &AtomicUsize) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+}
+
+fn labelled_counter(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &[&str],
+    counters: &[&AtomicUsize],
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (value, count) in values.iter().zip(counters.iter()) {
+        let _ = writeln!(
+            out,
+            "{}{{{}=\"{}\"}} {}",
+            name,
+            label,
+            value,
+            count.load(Ordering::Relaxed)
+        );
+    }
+}
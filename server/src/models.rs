@@ -1,15 +1,83 @@
+use crate::gameplay::game_actor::GameHandle;
+use game::gameplay::{DDPState, DameDePiqueGameBuilder, OpeningRule, OpeningThreshold, PlayerMove};
+use game::rules::GameRules;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::{mpsc, RwLock};
+use uuid::Uuid;
 use warp::ws::Message;
 
-pub type GameSessions = Arc<RwLock<HashMap<String, GameSession>>>;
+/// Every game session runs in its own task, see `GameHandle`.
+///
+/// Locks are always taken in the same order: the collection of game sessions,
+/// then the collection of players. The collection of game sessions is only held
+/// long enough to find, add or remove a session, never while waiting on one.
+pub type GameSessions = Arc<RwLock<HashMap<String, GameHandle>>>;
 pub type Players = Arc<RwLock<HashMap<String, Player>>>;
 
+/// Returns a game session without keeping the collection of game sessions locked
+pub async fn find_session(sessions: &GameSessions, game_id: &str) -> Option<GameHandle> {
+    sessions.read().await.get(game_id).cloned()
+}
+
+/// Number of seats at a table
+pub const SEATS: usize = 4;
+
+/// Characters of invite codes, leaving out the ones easily mistaken for each other
+const INVITE_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const INVITE_CODE_LENGTH: usize = 6;
+
+/// Returns the table designated by its invite code or, for public tables, by its identifier
+///
+/// ## Arguments
+/// `game_sessions` - the locked collection of game sessions
+/// `game_id` - the game identifier, must match the table of the invite code when both are given
+/// `invite_code` - the code of the table, case insensitive
+pub fn find_table(
+    game_sessions: &HashMap<String, GameHandle>,
+    game_id: Option<&str>,
+    invite_code: Option<&str>,
+) -> Option<(String, GameHandle)> {
+    match invite_code {
+        Some(code) => game_sessions
+            .iter()
+            .find(|(id, game)| {
+                game.invite_code.eq_ignore_ascii_case(code.trim())
+                    && game_id.map_or(true, |game_id| game_id == id.as_str())
+            })
+            .map(|(id, game)| (id.clone(), game.clone())),
+        None => game_id
+            .and_then(|game_id| game_sessions.get_key_value(game_id))
+            .filter(|(_, game)| !game.private)
+            .map(|(id, game)| (id.clone(), game.clone())),
+    }
+}
+
+/// Generates an invite code no other table uses
+///
+/// ## Arguments
+/// `game_sessions` - the locked collection of game sessions
+pub fn new_invite_code(game_sessions: &HashMap<String, GameHandle>) -> String {
+    loop {
+        let code: String = Uuid::new_v4()
+            .as_bytes()
+            .iter()
+            .take(INVITE_CODE_LENGTH)
+            .map(|byte| INVITE_CODE_ALPHABET[*byte as usize % INVITE_CODE_ALPHABET.len()] as char)
+            .collect();
+        if game_sessions.values().all(|game| game.invite_code != code) {
+            return code;
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Player {
     pub sender: Option<mpsc::UnboundedSender<std::result::Result<Message, warp::Error>>>,
+    /// Identifies the websocket `sender` belongs to, 0 before the player first connects
+    pub connection: usize,
     pub inner: PlayerResponse,
 }
 
@@ -18,12 +86,302 @@ pub struct GameSession {
     pub inner: GameResponse,
     pub state: game::gameplay::DDPState,
     pub is_active: bool,
+    /// Number of moves before the hands are revealed to spectators,
+    /// `None` when the table doesn't allow coaching
+    pub coach_delay: Option<usize>,
+    /// Every player's hand after each of the most recent moves
+    pub hand_history: VecDeque<Vec<Vec<game::models::Card>>>,
+    pub moves_played: usize,
+    /// Players whose websocket closed during the game, and when it closed
+    pub disconnected: HashMap<String, Instant>,
+    /// Seats played by a bot after their reservation expired
+    pub bot_seats: HashSet<usize>,
+    /// Whether the player whose turn it is already drew a card
+    pub turn_drawn: bool,
+    /// When the player whose turn it is started their turn
+    pub turn_started_at: Instant,
+    /// Whether the round ended and the table is waiting for the next one
+    pub round_over: bool,
+    /// Whether a team won the game
+    pub finished: bool,
+    /// Last time anyone joined, started or played at the table
+    pub last_activity: Instant,
+}
+
+impl GameSession {
+    /// Creates a game session in the lobby, waiting to be started
+    ///
+    /// ## Arguments
+    /// `game_id` - a unique game identifier
+    /// `invite_code` - the short code players join the table with
+    /// `private` - whether the table is hidden from the lobby
+    /// `variant` - the rules the table plays by
+    /// `players` - the usernames of the players, ordered by seat
+    /// `coach_delay` - moves before hands are revealed to spectators, if allowed
+    /// `turn_timer` - the turn clock of the table, if any
+    pub fn new(
+        game_id: String,
+        invite_code: String,
+        private: bool,
+        variant: RuleVariant,
+        players: Vec<String>,
+        coach_delay: Option<usize>,
+        turn_timer: Option<TurnTimerSettings>,
+    ) -> Self {
+        let mut state = DameDePiqueGameBuilder::initialize_game_with_players(&players).unwrap();
+        variant.apply(&mut state);
+        Self {
+            is_active: false,
+            state,
+            inner: GameResponse {
+                game_id,
+                invite_code,
+                private,
+                variant,
+                players,
+                spectators: Vec::new(),
+                turn_timer,
+                undo_allowed: true,
+                hints_allowed: true,
+            },
+            coach_delay,
+            hand_history: VecDeque::new(),
+            moves_played: 0,
+            disconnected: HashMap::new(),
+            bot_seats: HashSet::new(),
+            turn_drawn: false,
+            turn_started_at: Instant::now(),
+            round_over: false,
+            finished: false,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// Passes the turn to the next player
+    pub fn end_turn(&mut self) {
+        PlayerMove::end_turn(&mut self.state);
+        self.turn_drawn = false;
+        self.turn_started_at = Instant::now();
+        self.record_move();
+    }
+
+    /// Resets the turn tracking at the start of a round
+    pub fn start_round(&mut self) {
+        self.turn_drawn = false;
+        self.turn_started_at = Instant::now();
+        self.round_over = false;
+        self.finished = false;
+        self.reset_history();
+        self.touch();
+    }
+
+    /// Returns how the table is listed in the lobby
+    pub fn lobby_entry(&self) -> LobbyEntry {
+        let seats_taken = self.inner.players.len();
+        let status = if self.is_active {
+            TableStatus::InProgress
+        } else if seats_taken >= SEATS {
+            TableStatus::Full
+        } else {
+            TableStatus::Open
+        };
+        LobbyEntry {
+            game_id: self.inner.game_id.clone(),
+            host: self.inner.players.first().cloned().unwrap_or_default(),
+            players: self.inner.players.clone(),
+            seats_taken,
+            seats_open: SEATS.saturating_sub(seats_taken),
+            spectators: self.inner.spectators.len(),
+            status,
+            variant: self.inner.variant,
+        }
+    }
+
+    /// Returns the seat of the player at the table
+    pub fn seat_of(&self, username: &str) -> Option<usize> {
+        self.inner
+            .players
+            .iter()
+            .position(|player| player == username)
+    }
+
+    /// Snapshots the players' hands after a move was played, debug builds
+    /// also check the table didn't lose track of a card of the shoe
+    pub fn record_move(&mut self) {
+        self.moves_played += 1;
+        debug_assert!(
+            self.state.unaccounted_cards().is_empty(),
+            "Game {} lost track of cards after move {}: {:?}",
+            self.inner.game_id,
+            self.moves_played,
+            self.state.unaccounted_cards()
+        );
+        self.snapshot_hands();
+        self.touch();
+    }
+
+    /// Marks the session as being in use
+    pub fn touch(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    /// Resets the move history at the start of a round
+    pub fn reset_history(&mut self) {
+        self.moves_played = 0;
+        self.hand_history.clear();
+        self.snapshot_hands();
+    }
+
+    /// Returns the hands as they were `coach_delay` moves ago
+    ///
+    /// ## Returns
+    /// None if coaching is disabled or not enough moves were played yet
+    pub fn coach_hands(&self) -> Option<&Vec<Vec<game::models::Card>>> {
+        if self.moves_played < self.coach_delay? {
+            return None;
+        }
+        self.hand_history.front()
+    }
+
+    fn snapshot_hands(&mut self) {
+        if let Some(delay) = self.coach_delay {
+            self.hand_history.push_back(
+                self.state
+                    .default_state
+                    .players
+                    .iter()
+                    .map(|player| player.hand.clone())
+                    .collect(),
+            );
+            while self.hand_history.len() > delay + 1 {
+                self.hand_history.pop_front();
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
 pub struct GameResponse {
     pub game_id: String,
-    pub players: HashSet<String>,
+    /// Short code shared with friends to join the table
+    pub invite_code: String,
+    /// Private tables are hidden from the lobby and joined with their invite code
+    pub private: bool,
+    pub variant: RuleVariant,
+    /// The usernames of the players, ordered by seat
+    pub players: Vec<String>,
+    /// The usernames of the people watching the table
+    pub spectators: Vec<String>,
+    pub turn_timer: Option<TurnTimerSettings>,
+    /// Whether players may take back their last meld of the turn, set by the host
+    pub undo_allowed: bool,
+    /// Whether players may ask for meld suggestions, set by the host
+    pub hints_allowed: bool,
+}
+
+/// Rules a table plays by
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleVariant {
+    Classic,
+    /// Teams open with a set worth at least 50 points, 25 more for every 500 points they have
+    MinimumPoints,
+}
+
+impl RuleVariant {
+    /// Sets the rules of the variant on a newly dealt game
+    pub fn apply(&self, state: &mut DDPState) {
+        state.opening = match self {
+            RuleVariant::Classic => OpeningRule::Sets,
+            RuleVariant::MinimumPoints => OpeningRule::Points(OpeningThreshold {
+                minimum_points: 50,
+                raise_every: 500,
+                raise_by: 25,
+            }),
+        };
+    }
+}
+
+impl Default for RuleVariant {
+    fn default() -> Self {
+        RuleVariant::Classic
+    }
+}
+
+/// Where a table stands, as filtered in the lobby
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableStatus {
+    /// Waiting for players
+    Open,
+    /// Every seat is taken, waiting for the game to start
+    Full,
+    InProgress,
+}
+
+/// A public table as listed in the lobby
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LobbyEntry {
+    pub game_id: String,
+    pub host: String,
+    /// The usernames of the players, ordered by seat
+    pub players: Vec<String>,
+    pub seats_taken: usize,
+    pub seats_open: usize,
+    /// Number of people watching the table
+    pub spectators: usize,
+    pub status: TableStatus,
+    pub variant: RuleVariant,
+}
+
+/// Filters and page of the lobby listing, pages start at 1
+#[derive(Deserialize, Debug)]
+pub struct LobbyQuery {
+    #[serde(default)]
+    pub status: Option<TableStatus>,
+    #[serde(default)]
+    pub variant: Option<RuleVariant>,
+    #[serde(default = "default_page")]
+    pub page: usize,
+    #[serde(default = "default_per_page")]
+    pub per_page: usize,
+}
+
+fn default_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    20
+}
+
+/// Turn clock of a table, a default move is played
+/// for the player when their time runs out
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TurnTimerSettings {
+    pub turn_seconds: u64,
+    /// Remaining seconds at which the table is warned
+    #[serde(default = "default_warning_seconds")]
+    pub warning_seconds: Vec<u64>,
+}
+
+fn default_warning_seconds() -> Vec<u64> {
+    vec![10]
+}
+
+impl TurnTimerSettings {
+    /// Returns whether the clock leaves the players time to play
+    ///
+    /// ## Rules
+    /// * A turn lasts at least a second
+    /// * The table is warned before the time runs out, and after the turn started
+    pub fn is_valid(&self) -> bool {
+        self.turn_seconds > 0
+            && self
+                .warning_seconds
+                .iter()
+                .all(|seconds| *seconds > 0 && *seconds < self.turn_seconds)
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -38,15 +396,66 @@ pub struct RegisterPlayerRequest {
     pub username: String,
 }
 
+/// Creates a table when neither the game identifier nor the invite code is given,
+/// joins the table they designate otherwise
 #[derive(Deserialize, Debug)]
 pub struct RegisterGameRequest {
-    pub game_identifier: String,
+    #[serde(default)]
+    pub game_identifier: Option<String>,
+    #[serde(default)]
+    pub invite_code: Option<String>,
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub variant: RuleVariant,
+    pub player_username: String,
+    #[serde(default)]
+    pub coach_delay: Option<usize>,
+    #[serde(default)]
+    pub turn_timer: Option<TurnTimerSettings>,
+    #[serde(default = "allowed_by_default")]
+    pub allow_undo: bool,
+    #[serde(default = "allowed_by_default")]
+    pub allow_hints: bool,
+}
+
+fn allowed_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CloseGameRequest {
+    pub game_id: String,
+    pub player_username: String,
+}
+
+/// Changes the rules of the table, only the host may, rules left out are kept
+#[derive(Deserialize, Debug)]
+pub struct TableRulesRequest {
+    pub game_id: String,
     pub player_username: String,
+    #[serde(default)]
+    pub allow_undo: Option<bool>,
+    #[serde(default)]
+    pub allow_hints: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SpectateGameRequest {
+    pub game_id: String,
+    pub username: String,
+    /// Required to watch a private table
+    #[serde(default)]
+    pub invite_code: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
-pub struct GameSessionListResponse {
-    pub games: Vec<GameResponse>,
+pub struct LobbyResponse {
+    pub games: Vec<LobbyEntry>,
+    pub page: usize,
+    pub per_page: usize,
+    /// Number of tables matching the filters, across every page
+    pub total: usize,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -57,6 +466,29 @@ pub struct StartGameRequest {
 #[derive(Serialize, Debug)]
 pub struct StartGameResponse;
 
+/// Notice sent to the table when its game session is removed
+#[derive(Serialize, Debug, Clone)]
+pub struct GameClosedResponse {
+    pub game_id: String,
+    pub reason: String,
+}
+
+/// Notice sent to the table while a player's turn clock runs down
+#[derive(Serialize, Debug, Clone)]
+pub struct TurnTimerResponse {
+    pub username: String,
+    pub seat: usize,
+    pub seconds_remaining: u64,
+}
+
+/// Notice sent to the table when a seat's connection changes
+#[derive(Serialize, Debug, Clone)]
+pub struct SeatStatusResponse {
+    pub username: String,
+    pub seat: usize,
+    pub grace_seconds: Option<u64>,
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct WebSocketResponse<S: Serialize> {
     pub response_type: String,
@@ -71,3 +503,45 @@ impl warp::reject::Reject for TooManyPlayers {}
 #[derive(Debug)]
 pub struct PlayerExists;
 impl warp::reject::Reject for PlayerExists {}
+
+#[derive(Debug)]
+pub struct AlreadySeated;
+impl warp::reject::Reject for AlreadySeated {}
+
+#[derive(Debug)]
+pub struct NotGameHost;
+impl warp::reject::Reject for NotGameHost {}
+
+#[derive(Debug)]
+pub struct ServerShuttingDown;
+impl warp::reject::Reject for ServerShuttingDown {}
+
+#[derive(Debug)]
+pub struct TooManyRequests;
+impl warp::reject::Reject for TooManyRequests {}
+
+#[derive(Debug)]
+pub struct TooManySessions;
+impl warp::reject::Reject for TooManySessions {}
+
+#[derive(Debug)]
+pub struct InvalidUsername;
+impl warp::reject::Reject for InvalidUsername {}
+
+#[derive(Debug)]
+pub struct UndoDisabled;
+impl warp::reject::Reject for UndoDisabled {}
+
+#[derive(Debug)]
+pub struct HintsDisabled;
+impl warp::reject::Reject for HintsDisabled {}
+
+/// Turn clock of a new table that doesn't leave the players time to play
+#[derive(Debug)]
+pub struct InvalidTurnTimer;
+impl warp::reject::Reject for InvalidTurnTimer {}
+
+/// Cards of a move that aren't in the player's hand or are given more than once
+#[derive(Debug)]
+pub struct InvalidCards;
+impl warp::reject::Reject for InvalidCards {}
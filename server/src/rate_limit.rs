@@ -0,0 +1,106 @@
+use crate::config::Config;
+use crate::models::TooManyRequests;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+
+/// Number of tracked clients past which expired windows are dropped
+const PURGE_THRESHOLD: usize = 10_000;
+
+pub type SharedRateLimiter = Arc<RateLimiter>;
+
+/// Fixed window rate limits per IP address and per player
+pub struct RateLimiter {
+    window: Duration,
+    ip_limit: u32,
+    player_limit: u32,
+    /// Start of the current window and requests made in it, by client
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl From<&Config> for RateLimiter {
+    fn from(config: &Config) -> Self {
+        Self {
+            window: Duration::from_secs(config.rate_limit_window_seconds),
+            ip_limit: config.ip_requests_per_window,
+            player_limit: config.player_requests_per_window,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Counts a request from the address, returns whether it is allowed
+    pub fn allow_ip(&self, ip: IpAddr) -> bool {
+        self.allow(format!("ip:{}", ip), self.ip_limit)
+    }
+
+    /// Counts a request from the player, returns whether it is allowed
+    pub fn allow_player(&self, username: &str) -> bool {
+        self.allow(format!("player:{}", username), self.player_limit)
+    }
+
+    fn allow(&self, client: String, limit: u32) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > PURGE_THRESHOLD {
+            let window = self.window;
+            windows.retain(|_, (started_at, _)| now.duration_since(*started_at) < window);
+        }
+
+        let (started_at, requests) = windows.entry(client).or_insert((now, 0));
+        if now.duration_since(*started_at) >= self.window {
+            *started_at = now;
+            *requests = 0;
+        }
+        *requests += 1;
+        *requests <= limit
+    }
+}
+
+/// Rejects requests once their IP address went over its rate limit
+///
+/// ## Arguments
+/// `limiter` - the rate limits shared by every route
+pub fn with_ip_limit(
+    limiter: SharedRateLimiter,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and_then(move |address: Option<SocketAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                match address {
+                    Some(address) if !limiter.allow_ip(address.ip()) => {
+                        Err(warp::reject::custom(TooManyRequests))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_allowed_again_once_the_window_rolls_over() {
+        let limiter = RateLimiter {
+            window: Duration::from_millis(50),
+            ip_limit: 1,
+            player_limit: 1,
+            windows: Mutex::new(HashMap::new()),
+        };
+        assert!(limiter.allow_player("north"));
+        assert!(!limiter.allow_player("north"));
+        // Players are counted separately
+        assert!(limiter.allow_player("east"));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.allow_player("north"));
+        assert!(!limiter.allow_player("north"));
+    }
+}
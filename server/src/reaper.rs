@@ -0,0 +1,123 @@
+use crate::config::{Config, SharedConfig};
+use crate::gameplay::game_actor::{GameCommand, GameHandle};
+use crate::metrics;
+use crate::models::{GameSession, GameSessions};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::time::interval;
+
+/// Why a game session was removed
+#[derive(Debug, Clone, Copy)]
+pub enum CloseReason {
+    Idle,
+    Finished,
+    Abandoned,
+    ClosedByHost,
+    ServerShutdown,
+}
+
+impl CloseReason {
+    fn counter(self) -> &'static AtomicUsize {
+        match self {
+            CloseReason::Idle => &metrics::SESSIONS_REAPED_IDLE,
+            CloseReason::Finished => &metrics::SESSIONS_REAPED_FINISHED,
+            CloseReason::Abandoned => &metrics::SESSIONS_REAPED_ABANDONED,
+            CloseReason::ClosedByHost => &metrics::SESSIONS_CLOSED,
+            CloseReason::ServerShutdown => &metrics::SESSIONS_SHUT_DOWN,
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CloseReason::Idle => write!(f, "The game was inactive for too long"),
+            CloseReason::Finished => write!(f, "The game is over"),
+            CloseReason::Abandoned => write!(f, "Every player left the game"),
+            CloseReason::ClosedByHost => write!(f, "The host closed the game"),
+            CloseReason::ServerShutdown => write!(f, "The server is shutting down"),
+        }
+    }
+}
+
+/// Periodically removes the game sessions nobody is using anymore
+///
+/// ## Purpose
+/// Removes game sessions that every player left, finished games once
+/// they've been kept long enough and games without any recent activity
+///
+/// ## Arguments
+/// `config` - the server configuration
+/// `sessions` - persistent collection of game sessions
+pub async fn run_reaper(config: SharedConfig, sessions: GameSessions) {
+    let mut ticks = interval(Duration::from_secs(config.reaper_interval_seconds));
+
+    loop {
+        ticks.tick().await;
+        let tables: Vec<(String, GameHandle)> = sessions
+            .read()
+            .await
+            .iter()
+            .map(|(game_id, game)| (game_id.clone(), game.clone()))
+            .collect();
+
+        let mut expired = Vec::new();
+        for (game_id, game) in tables {
+            let session = match game.request(|reply| GameCommand::Snapshot { reply }).await {
+                Some(session) => session,
+                None => continue,
+            };
+            if let Some(reason) = reap_reason(&session, &config) {
+                expired.push((game_id, reason));
+            }
+        }
+
+        if expired.is_empty() {
+            continue;
+        }
+        for (game_id, reason) in expired.iter() {
+            println!("Reaping game {}: {}", game_id, reason);
+            close_session(game_id, *reason, &sessions).await;
+        }
+        println!(
+            "Reaped {} game sessions, {} in total",
+            expired.len(),
+            metrics::sessions_reaped()
+        );
+    }
+}
+
+/// Returns why the game session should be removed, if it should
+fn reap_reason(session: &GameSession, config: &Config) -> Option<CloseReason> {
+    let idle_seconds = session.last_activity.elapsed().as_secs();
+    if session.inner.players.is_empty() {
+        Some(CloseReason::Abandoned)
+    } else if session.finished && idle_seconds >= config.finished_session_ttl_seconds {
+        Some(CloseReason::Finished)
+    } else if idle_seconds >= config.idle_session_ttl_seconds {
+        Some(CloseReason::Idle)
+    } else {
+        None
+    }
+}
+
+/// Removes a game session and releases everyone at the table
+///
+/// ## Arguments
+/// `game_id` - the game session being removed
+/// `reason` - why the game session is removed, sent to the table
+/// `sessions` - persistent collection of game sessions
+///
+/// ## Returns
+/// Whether the game session existed
+pub async fn close_session(game_id: &str, reason: CloseReason, sessions: &GameSessions) -> bool {
+    let game = match sessions.write().await.remove(game_id) {
+        Some(game) => game,
+        None => return false,
+    };
+    reason.counter().fetch_add(1, Ordering::Relaxed);
+    // The game session lets everyone at the table know before stopping
+    game.notify(GameCommand::Close { reason });
+    true
+}
@@ -0,0 +1,122 @@
+use crate::config::SharedConfig;
+use crate::gameplay::game_actor::{GameCommand, GameHandle};
+use crate::gameplay::gameplay_models::GameSessionSnapshot;
+use crate::models::{GameSessions, Players, WebSocketResponse};
+use crate::reaper::{self, CloseReason};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::signal;
+use warp::ws::Message;
+
+/// Set once the server started shutting down, no new games are accepted after that
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether the server is shutting down
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+/// Waits for SIGINT or SIGTERM, then drains the server
+///
+/// ## Purpose
+/// Stops accepting new games, lets every connected player know the server
+/// is going away, saves every game session to local storage and closes them.
+/// The server stops once this returns.
+///
+/// ## Arguments
+/// `config` - the server configuration
+/// `players` - persistent collection of players
+/// `sessions` - persistent collection of game sessions
+pub async fn shutdown_on_signal(config: SharedConfig, players: Players, sessions: GameSessions) {
+    wait_for_signal().await;
+    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+    println!("Shutting down");
+
+    notify_players(&players).await;
+
+    let snapshots = drain_sessions(&sessions).await;
+    match save_snapshots(&config.snapshot_dir, &snapshots) {
+        Ok(path) => println!(
+            "Saved {} game sessions to {}",
+            snapshots.len(),
+            path.display()
+        ),
+        Err(e) => eprintln!("Could not save the game sessions: {}", e),
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            eprintln!("Could not listen for SIGTERM: {}", e);
+            let _ = signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = signal::ctrl_c().await;
+}
+
+/// Lets every connected player know the server is going away
+async fn notify_players(players: &Players) {
+    let message = serde_json::to_string(&WebSocketResponse {
+        response_type: "ServerShutdown".into(),
+        data: {},
+    })
+    .unwrap();
+    for player in players.read().await.values() {
+        if let Some(sender) = &player.sender {
+            // The player may have disconnected in the meantime
+            let _ = sender.send(Ok(Message::text(message.clone())));
+        }
+    }
+}
+
+/// Snapshots then closes every game session
+///
+/// ## Returns
+/// The snapshot of every game session that was still running
+async fn drain_sessions(sessions: &GameSessions) -> Vec<GameSessionSnapshot> {
+    let tables: Vec<(String, GameHandle)> = sessions
+        .read()
+        .await
+        .iter()
+        .map(|(game_id, game)| (game_id.clone(), game.clone()))
+        .collect();
+
+    let mut snapshots = Vec::with_capacity(tables.len());
+    for (game_id, game) in tables {
+        if let Some(session) = game.request(|reply| GameCommand::Snapshot { reply }).await {
+            snapshots.push(GameSessionSnapshot::from(&session));
+        }
+        reaper::close_session(&game_id, CloseReason::ServerShutdown, sessions).await;
+    }
+    snapshots
+}
+
+/// Writes the snapshots to a new file of the snapshot directory
+///
+/// ## Returns
+/// The path of the file written
+fn save_snapshots(directory: &str, snapshots: &[GameSessionSnapshot]) -> io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let path = PathBuf::from(directory).join(format!(
+        "sessions-{}.json",
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+    ));
+    fs::write(&path, serde_json::to_string_pretty(snapshots)?)?;
+    Ok(path)
+}
@@ -1,21 +1,29 @@
-use crate::models::{
-    GameSession, GameSessions, Player, Players, StartGameResponse, WebSocketResponse,
-};
+use crate::config::SharedConfig;
+use crate::gameplay::game_actor::{GameCommand, GameHandle};
+use crate::models::{find_session, GameSessions, Player, Players};
 use futures::{FutureExt, StreamExt};
 use game::Game;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::from_str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio::time::delay_for;
 use warp::ws::{Message, WebSocket};
 
+/// Identifier of the most recent websocket connection
+static LAST_CONNECTION: AtomicUsize = AtomicUsize::new(0);
+
 pub async fn client_connection(
     ws: WebSocket,
     player_id: String,
+    config: SharedConfig,
     clients: Players,
     games: GameSessions,
     mut client: Player,
 ) {
+    let connection = LAST_CONNECTION.fetch_add(1, Ordering::Relaxed) + 1;
     let (client_ws_sender, mut client_ws_rcv) = ws.split();
     let (client_sender, client_rcv) = mpsc::unbounded_channel();
 
@@ -27,6 +35,7 @@ pub async fn client_connection(
     {
         let mut clients_writeable = clients.write().await;
         client.sender = Some(client_sender);
+        client.connection = connection;
         match clients_writeable.get_mut(&player_id) {
             Some(p) => *p = client,
             None => {
@@ -35,6 +44,7 @@ pub async fn client_connection(
         }
     }
     println!("{} connected", player_id);
+    resume_game(&player_id, &clients, &games).await;
 
     while let Some(result) = client_ws_rcv.next().await {
         let msg = match result {
@@ -53,20 +63,86 @@ pub async fn client_connection(
 
     println!("{} disconnected", player_id);
 
-    let mut players = clients.write().await;
-    let player = players.get(&player_id).unwrap();
-    let game_id_option = &player.inner.game_session_id;
+    // Nothing can be sent to the closed socket anymore, unless the player
+    // already reconnected on a new socket which then stays in use
+    let game_id_option = match clients.write().await.get_mut(&player_id) {
+        Some(player) if player.connection == connection => {
+            player.sender = None;
+            player.inner.game_session_id.clone()
+        }
+        _ => None,
+    };
     if let Some(game_id) = game_id_option {
-        if let Some(game) = games.write().await.get_mut(game_id) {
-            // a player has disconnected and wasn't part of an active game
-            if !game.is_active {
-                game.inner.players.remove(&player.inner.username);
-                players.remove(&player_id);
+        if let Some(game) = find_session(&games, &game_id).await {
+            let username = player_id.clone();
+            let reservation = game
+                .request(move |reply| GameCommand::Disconnect { username, reply })
+                .await
+                .flatten();
+            if let Some(disconnected_at) = reservation {
+                tokio::task::spawn(reserve_seat(
+                    player_id,
+                    disconnected_at,
+                    Duration::from_secs(config.reconnect_grace_seconds),
+                    game,
+                ));
             }
         }
     }
 }
 
+/// Holds a disconnected player's seat for the configured grace period
+///
+/// ## Purpose
+/// If the player hasn't reconnected once the grace period is over, the table
+/// is notified and the seat is handed to a bot when the server allows it
+///
+/// ## Arguments
+/// `player_id` - the player who disconnected
+/// `disconnected_at` - when the player's websocket closed
+/// `grace_period` - how long the seat is held
+/// `game` - the active game the player is seated at
+async fn reserve_seat(
+    player_id: String,
+    disconnected_at: Instant,
+    grace_period: Duration,
+    game: GameHandle,
+) {
+    delay_for(grace_period).await;
+
+    game.notify(GameCommand::ReservationExpired {
+        username: player_id,
+        disconnected_at,
+    });
+}
+
+/// Resumes an active game for a player connecting back to their seat
+///
+/// ## Purpose
+/// Takes the seat back from a bot if needed, lets the table know
+/// the player is back and pushes the full state of the game to the player
+///
+/// ## Arguments
+/// `player_id` - the player connecting
+/// `clients` - persistent collection of players
+/// `games` - persistent collection of game sessions
+async fn resume_game(player_id: &str, clients: &Players, games: &GameSessions) {
+    let game_id = match clients.read().await.get(player_id) {
+        Some(player) => player.inner.game_session_id.clone(),
+        None => None,
+    };
+    let game_id = match game_id {
+        Some(game_id) => game_id,
+        None => return,
+    };
+
+    if let Some(game) = find_session(games, &game_id).await {
+        game.notify(GameCommand::Reconnect {
+            username: player_id.to_string(),
+        });
+    }
+}
+
 async fn client_msg(id: &str, msg: Message, clients: &Players) {
     println!("received message from {}: {:?}", id, msg);
     let message = match msg.to_str() {